@@ -0,0 +1,5 @@
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    rs_level::cli::run(std::env::args().skip(1).collect(), None)
+}
@@ -0,0 +1,509 @@
+//! `lvl-tool`, for working with levels on machines without a display. Everything but PNG output
+//! works on `Level` alone, the editor binary passes in its renderer to add that.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::file::{hash_bytes, LevelIoError};
+use crate::{CollisionShape, Level, SpritesheetSource, TileIndex};
+
+const USAGE: &str = "\
+usage: lvl-tool [--spritesheet <image>] [--tiles <across>x<down>] [--compress] <command>
+
+commands:
+  validate <level>           check that a level loads and its tiles fit the spritesheet
+  stats <level>              print what a level contains
+  convert <input> <output>   convert between formats, picked by file extension, compressing
+                             .lvl output with --compress
+  render <level> <out.png>   render a level, leaving out tile layers and int grids with
+                             --no-layer <name> and the rest with --no-collision and
+                             --no-entities
+
+Levels are read from .lvl, .ron, .json, .tmx, .tmj and CSV .origin.json sidecars, and written
+to those and .ldtk, .png and .csv. --spritesheet and --tiles stand in for a spritesheet the level
+doesn't record. PNG output needs the editor's renderer, run the same commands as
+`rs-level-editor <command>` for it.";
+
+/// Draws a level to a PNG at the given path, see `run`.
+pub type Renderer = fn(&Level, &RenderOptions, &Path) -> Result<(), LevelIoError>;
+
+/// Which layers end up in a rendered image.
+#[derive(Clone)]
+pub struct RenderOptions {
+    /// Whether each of `Level::layers` is drawn, by index.
+    pub layers: Vec<bool>,
+    /// Whether each of `Level::int_grids` is drawn, by index.
+    pub int_grids: Vec<bool>,
+    pub collision: bool,
+    pub entities: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Level,
+    Json,
+    Tiled,
+    Csv,
+    Ldtk,
+    Png,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".origin.json") {
+            return Some(Format::Csv);
+        }
+        match path
+            .extension()?
+            .to_string_lossy()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "lvl" | "ron" => Some(Format::Level),
+            "json" => Some(Format::Json),
+            "tmx" | "tmj" => Some(Format::Tiled),
+            "csv" => Some(Format::Csv),
+            "ldtk" => Some(Format::Ldtk),
+            "png" => Some(Format::Png),
+            _ => None,
+        }
+    }
+}
+
+/// A failed command, reported on stderr before exiting.
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+fn io_error(action: &str, path: &Path, why: LevelIoError) -> CliError {
+    CliError::Failed(format!("couldn't {} {}: {}", action, path.display(), why))
+}
+
+struct Options {
+    spritesheet: Option<PathBuf>,
+    tiles: Option<(u8, u8)>,
+    compress: bool,
+    /// Names of the tile layers and int grids left out of rendered images, matched ignoring case.
+    hidden_layers: Vec<String>,
+    collision: bool,
+    entities: bool,
+    positional: Vec<String>,
+}
+
+fn parse_tiles(value: &str) -> Option<(u8, u8)> {
+    let (across, down) = value.split_once('x')?;
+    let across: u8 = across.parse().ok()?;
+    let down: u8 = down.parse().ok()?;
+    (across > 0 && down > 0).then_some((across, down))
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, CliError> {
+    let mut options = Options {
+        spritesheet: None,
+        tiles: None,
+        compress: false,
+        hidden_layers: Vec::new(),
+        collision: true,
+        entities: true,
+        positional: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| CliError::Usage(format!("{} needs a value", flag)))
+        };
+        match arg.as_str() {
+            "--spritesheet" => options.spritesheet = Some(value("--spritesheet")?.into()),
+            "--tiles" => {
+                let tiles = value("--tiles")?;
+                options.tiles = Some(parse_tiles(&tiles).ok_or_else(|| {
+                    CliError::Usage(format!("--tiles wants <across>x<down>, not {}", tiles))
+                })?);
+            }
+            "--compress" => options.compress = true,
+            "--no-layer" => options.hidden_layers.push(value("--no-layer")?),
+            "--no-collision" => options.collision = false,
+            "--no-entities" => options.entities = false,
+            "-h" | "--help" => return Err(CliError::Usage(String::new())),
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option {}", flag)))
+            }
+            _ => options.positional.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+/// An empty level with `--spritesheet` and `--tiles` applied, which a level that records its own
+/// spritesheet overrides when loaded.
+fn cli_base_level(options: &Options) -> Result<Level, CliError> {
+    let mut level = Level::default();
+    if let Some((across, down)) = options.tiles {
+        level.spritesheet_info.num_rows = across;
+        level.spritesheet_info.num_cols = down;
+    }
+    if let Some(path) = &options.spritesheet {
+        let bytes = std::fs::read(path).map_err(|why| io_error("read", path, why.into()))?;
+        let (width, height) = image::load_from_memory(&bytes)
+            .map(|image| (image.width(), image.height()))
+            .map_err(|why| {
+                let why = std::io::Error::new(std::io::ErrorKind::InvalidData, why);
+                io_error("read", path, why.into())
+            })?;
+        level.spritesheet = Some(SpritesheetSource {
+            path: path.clone(),
+            width,
+            height,
+            hash: Some(hash_bytes(&bytes)),
+        });
+    }
+    Ok(level)
+}
+
+/// Loads any readable format over `base` and returns warnings about the result.
+fn load(path: &Path, base: Level) -> Result<(Level, Vec<String>), CliError> {
+    let (level, mut warnings) = match Format::of(path) {
+        Some(Format::Level) => Level::open(path, base).map(|level| (level, Vec::new())),
+        Some(Format::Json) => Level::import_json(path, base).map(|level| (level, Vec::new())),
+        Some(Format::Tiled) => Level::import_tiled(path),
+        Some(Format::Csv) => Level::import_csv(path, base).map(|level| (level, Vec::new())),
+        _ => {
+            return Err(CliError::Usage(format!(
+                "{} isn't a level format lvl-tool can read",
+                path.display()
+            )))
+        }
+    }
+    .map_err(|why| io_error("open", path, why))?;
+    // the same checks the editor makes when it loads the spritesheet
+    if let Some(source) = &level.spritesheet {
+        match std::fs::read(&source.path) {
+            Ok(bytes) if source.hash.is_some_and(|hash| hash != hash_bytes(&bytes)) => warnings
+                .push(format!(
+                    "Spritesheet {} has changed since this level was saved",
+                    source.path.display()
+                )),
+            Ok(_) => {}
+            Err(_) => warnings.push(format!(
+                "Spritesheet {} wasn't found",
+                source.path.display()
+            )),
+        }
+    }
+    Ok((level, warnings))
+}
+
+/// The layers `options` leaves in `level`.
+fn render_options(level: &Level, options: &Options) -> Result<RenderOptions, CliError> {
+    let is_hidden = |name: &str| {
+        options
+            .hidden_layers
+            .iter()
+            .any(|hidden| hidden.eq_ignore_ascii_case(name))
+    };
+    let is_layer = |hidden: &String| {
+        let mut names = level.layers.iter().map(|layer| &layer.name);
+        let mut int_grid_names = level.int_grids.iter().map(|layer| &layer.name);
+        names.any(|name| name.eq_ignore_ascii_case(hidden))
+            || int_grid_names.any(|name| name.eq_ignore_ascii_case(hidden))
+    };
+    if let Some(unknown) = options
+        .hidden_layers
+        .iter()
+        .find(|hidden| !is_layer(hidden))
+    {
+        return Err(CliError::Failed(format!(
+            "the level has no layer {}",
+            unknown
+        )));
+    }
+    Ok(RenderOptions {
+        layers: level
+            .layers
+            .iter()
+            .map(|layer| !is_hidden(&layer.name))
+            .collect(),
+        int_grids: level
+            .int_grids
+            .iter()
+            .map(|layer| !is_hidden(&layer.name))
+            .collect(),
+        collision: options.collision,
+        entities: options.entities,
+    })
+}
+
+/// Returns a warning for everything the format couldn't hold.
+fn write(
+    level: &Level,
+    path: &Path,
+    layers: RenderOptions,
+    render: Option<Renderer>,
+) -> Result<Vec<String>, CliError> {
+    let result = match Format::of(path) {
+        Some(Format::Level) => level.save(path).map(|_| Vec::new()),
+        Some(Format::Json) => level.export_json(path).map(|_| Vec::new()),
+        Some(Format::Tiled) => level.export_tiled(path),
+        Some(Format::Csv) => level.export_csv(path).map(|_| Vec::new()),
+        Some(Format::Ldtk) => level.export_ldtk(path),
+        Some(Format::Png) => match render {
+            Some(render) => render(level, &layers, path).map(|_| Vec::new()),
+            None => {
+                return Err(CliError::Failed(
+                    "PNG output needs the editor, run rs-level-editor with the same command"
+                        .to_string(),
+                ))
+            }
+        },
+        None => {
+            return Err(CliError::Usage(format!(
+                "{} isn't a format lvl-tool can write",
+                path.display()
+            )))
+        }
+    };
+    result.map_err(|why| io_error("write", path, why))
+}
+
+/// Tiles that point past the edge of the spritesheet grid.
+fn out_of_range_tiles(level: &Level) -> Vec<String> {
+    let mut problems = Vec::new();
+    for layer in &level.layers {
+        for (point, tile) in &layer.tiles {
+            if tile.col >= level.spritesheet_info.num_rows as u16
+                || tile.row >= level.spritesheet_info.num_cols as u16
+            {
+                problems.push(format!(
+                    "{} tile at ({}, {}) uses column {}, row {}, outside the {}x{} spritesheet",
+                    layer.name,
+                    point.x,
+                    point.y,
+                    tile.col,
+                    tile.row,
+                    level.spritesheet_info.num_rows,
+                    level.spritesheet_info.num_cols
+                ));
+            }
+        }
+    }
+    problems.sort();
+    problems
+}
+
+fn print_stats(level: &Level) {
+    println!("layers: {}", level.layers.len());
+    for layer in &level.layers {
+        let distinct: HashSet<&TileIndex> = layer.tiles.values().collect();
+        println!(
+            "  {}: {} tiles, {} distinct",
+            layer.name,
+            layer.tiles.len(),
+            distinct.len()
+        );
+    }
+    let mut kinds = vec![0; level.collision_kinds.len()];
+    // by place in `CollisionShape::PRESETS`, polygons after them
+    let mut shapes: BTreeMap<usize, (String, usize)> = BTreeMap::new();
+    for cell in level.collision.values() {
+        if let Some(count) = kinds.get_mut(cell.kind as usize) {
+            *count += 1;
+        }
+        if !cell.shape.is_full() {
+            let order = CollisionShape::PRESETS
+                .iter()
+                .position(|preset| *preset == cell.shape)
+                .unwrap_or(CollisionShape::PRESETS.len());
+            shapes.entry(order).or_insert((cell.shape.name(), 0)).1 += 1;
+        }
+    }
+    let kinds: Vec<String> = level
+        .collision_kinds
+        .iter()
+        .zip(kinds)
+        .filter(|(_, count)| *count > 0)
+        .map(|(kind, count)| format!("{} x{}", kind.name, count))
+        .collect();
+    if kinds.is_empty() {
+        println!("collision: 0 cells");
+    } else {
+        println!(
+            "collision: {} cells ({})",
+            level.collision.len(),
+            kinds.join(", ")
+        );
+    }
+    if !shapes.is_empty() {
+        let shapes: Vec<String> = shapes
+            .into_values()
+            .map(|(name, count)| format!("{} x{}", name, count))
+            .collect();
+        println!("  not full: {}", shapes.join(", "));
+    }
+    if !level.int_grids.is_empty() {
+        println!("int grids: {}", level.int_grids.len());
+    }
+    for layer in &level.int_grids {
+        let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+        for value in layer.cells.values() {
+            *counts.entry(*value).or_default() += 1;
+        }
+        let counts: Vec<String> = counts
+            .into_iter()
+            .map(|(value, count)| {
+                let name = layer.value(value).map_or("", |value| value.name.as_str());
+                format!("{} {} x{}", value, name, count)
+            })
+            .collect();
+        println!(
+            "  {}: {} cells ({})",
+            layer.name,
+            layer.cells.len(),
+            counts.join(", ")
+        );
+    }
+    let mut labels: BTreeMap<&str, usize> = BTreeMap::new();
+    for label in level.entities.values() {
+        *labels.entry(label).or_default() += 1;
+    }
+    let labels: Vec<String> = labels
+        .into_iter()
+        .map(|(label, count)| format!("{} x{}", label, count))
+        .collect();
+    if labels.is_empty() {
+        println!("entities: 0");
+    } else {
+        println!("entities: {} ({})", level.entities.len(), labels.join(", "));
+    }
+    let bounds = level.bounds();
+    println!(
+        "bounds: x {} to {}, y {} to {} ({}x{} cells)",
+        bounds.min_x,
+        bounds.max_x,
+        bounds.min_y,
+        bounds.max_y,
+        bounds.width(),
+        bounds.height()
+    );
+    match &level.spritesheet {
+        Some(source) => println!(
+            "spritesheet: {}, {}x{} px, {}x{} tiles",
+            source.path.display(),
+            source.width,
+            source.height,
+            level.spritesheet_info.num_rows,
+            level.spritesheet_info.num_cols
+        ),
+        None => println!("spritesheet: none recorded"),
+    }
+    println!(
+        "compressed: {}",
+        if level.compressed { "yes" } else { "no" }
+    );
+}
+
+fn run_command(options: Options, render: Option<Renderer>) -> Result<(), CliError> {
+    let base = cli_base_level(&options)?;
+    let args: Vec<&str> = options.positional.iter().map(String::as_str).collect();
+    let warn = |warnings: Vec<String>| {
+        for warning in warnings {
+            eprintln!("warning: {}", warning);
+        }
+    };
+    match args.as_slice() {
+        ["validate", path] => {
+            let (level, warnings) = load(Path::new(path), base)?;
+            warn(warnings);
+            let problems = out_of_range_tiles(&level);
+            if !problems.is_empty() {
+                return Err(CliError::Failed(problems.join("\n")));
+            }
+            println!("{} is valid", path);
+        }
+        ["stats", path] => {
+            let (level, warnings) = load(Path::new(path), base)?;
+            warn(warnings);
+            print_stats(&level);
+        }
+        ["convert", input, output] => {
+            let (mut level, warnings) = load(Path::new(input), base)?;
+            warn(warnings);
+            level.compressed |= options.compress;
+            let layers = render_options(&level, &options)?;
+            warn(write(&level, Path::new(output), layers, render)?);
+        }
+        ["render", path, output] => {
+            if Format::of(Path::new(output)) != Some(Format::Png) {
+                return Err(CliError::Usage("render writes .png images".to_string()));
+            }
+            let (level, warnings) = load(Path::new(path), base)?;
+            warn(warnings);
+            let layers = render_options(&level, &options)?;
+            warn(write(&level, Path::new(output), layers, render)?);
+        }
+        _ => return Err(CliError::Usage(String::new())),
+    }
+    Ok(())
+}
+
+/// Runs `lvl-tool` with `args`, not including the program name. PNG output goes through
+/// `render`, and is refused without one.
+pub fn run(args: Vec<String>, render: Option<Renderer>) -> ExitCode {
+    let result = parse_options(args).and_then(|options| run_command(options, render));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Failed(why)) => {
+            eprintln!("error: {}", why);
+            ExitCode::from(1)
+        }
+        Err(CliError::Usage(why)) => {
+            if !why.is_empty() {
+                eprintln!("error: {}\n", why);
+            }
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{sample_level, test_dir};
+
+    fn convert(input: &Path, output: &Path) -> Result<(), CliError> {
+        let args = ["convert", input.to_str().unwrap(), output.to_str().unwrap()];
+        let options = parse_options(args.into_iter().map(String::from).collect())?;
+        run_command(options, None)
+    }
+
+    #[test]
+    fn formats_are_picked_by_extension() {
+        assert_eq!(
+            Format::of(Path::new("level.origin.json")),
+            Some(Format::Csv)
+        );
+        assert_eq!(Format::of(Path::new("level.JSON")), Some(Format::Json));
+        assert_eq!(Format::of(Path::new("level.ron")), Some(Format::Level));
+        assert_eq!(Format::of(Path::new("level")), None);
+    }
+
+    #[test]
+    fn converts_without_a_renderer() {
+        let dir = test_dir("cli-convert");
+        let level = sample_level(&dir);
+        let input = dir.join("level.lvl");
+        level.save(&input).unwrap();
+        let output = dir.join("level.json");
+        assert!(convert(&input, &output).is_ok());
+        let read = Level::import_json(&output, Level::default()).unwrap();
+        assert_eq!(read, level);
+        assert!(matches!(
+            convert(&input, &dir.join("level.png")),
+            Err(CliError::Failed(_))
+        ));
+    }
+}
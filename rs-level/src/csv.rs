@@ -0,0 +1,431 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::file::{check_grid_size, sorted_collision, LevelIoError};
+use crate::{
+    default_collision_kinds, CollisionCell, CollisionKind, CollisionShape, HashableVec2,
+    IntGridLayer, IntGridValue, Level, TileIndex, TileLayer, DEFAULT_LAYER_NAMES,
+};
+
+/// 2 replaced `background` and `foreground` with a list of named `layers`, 3 added
+/// `collision_kinds`, 4 added `int_grids`, 5 added `collision_shapes`.
+const CSV_FORMAT_VERSION: u32 = 5;
+const EMPTY_CELL: i64 = -1;
+
+/// Written next to the CSV grids. Column 0 and row 0 of every grid sit at (`origin_x`,
+/// `origin_y`) in level coordinates, with rows counting down.
+#[derive(Serialize, Deserialize)]
+struct CsvSidecar {
+    format_version: u32,
+    origin_x: i64,
+    origin_y: i64,
+    width: i64,
+    height: i64,
+    /// Tiles across the spritesheet, to turn tileset indices back into columns and rows.
+    columns: u16,
+    /// Bottom first.
+    #[serde(default)]
+    layers: Vec<CsvLayer>,
+    /// File names of version 1's two grids.
+    #[serde(default, skip_serializing)]
+    background: String,
+    #[serde(default, skip_serializing)]
+    foreground: String,
+    /// The collision grid holds 0 for empty cells and otherwise one more than the cell's index
+    /// into this list, so version 2's 1 for solid reads back as the first kind.
+    #[serde(default = "default_collision_kinds")]
+    collision_kinds: Vec<CollisionKind>,
+    collision: String,
+    /// The shape of every collision cell that isn't full, by its place in the grids.
+    #[serde(default)]
+    collision_shapes: Vec<CsvCollisionShape>,
+    #[serde(default)]
+    int_grids: Vec<CsvIntGrid>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CsvCollisionShape {
+    col: i64,
+    row: i64,
+    shape: CollisionShape,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CsvLayer {
+    name: String,
+    /// File name of the grid, relative to the sidecar like every other file name in it.
+    file: String,
+}
+
+/// The grid holds each cell's value, 0 for empty cells.
+#[derive(Serialize, Deserialize)]
+struct CsvIntGrid {
+    name: String,
+    file: String,
+    values: Vec<IntGridValue>,
+}
+
+fn invalid(why: impl ToString) -> LevelIoError {
+    LevelIoError::InvalidCsv(why.to_string())
+}
+
+/// `level.csv` becomes `level.<suffix>`, next to it.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}", stem, suffix))
+}
+
+fn write_grid(path: &Path, grid: &[Vec<i64>]) -> Result<(), LevelIoError> {
+    let mut text = String::new();
+    for row in grid {
+        let cells: Vec<String> = row.iter().map(|cell| cell.to_string()).collect();
+        text.push_str(&cells.join(","));
+        text.push('\n');
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Picks a file name suffix for every layer from its name, e.g. `Far Hills` becomes
+/// `far-hills.csv`, numbering the ones that would collide with each other or the collision grid.
+fn layer_suffixes<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut used: HashSet<String> = ["collision", "origin"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    names
+        .map(|name| {
+            let slug: String = name
+                .chars()
+                .map(|c| match c.is_alphanumeric() {
+                    true => c.to_ascii_lowercase(),
+                    false => '-',
+                })
+                .collect();
+            let slug = match slug.trim_matches('-') {
+                "" => "layer".to_string(),
+                slug => slug.to_string(),
+            };
+            let slug = match used.contains(&slug) {
+                true => (2..)
+                    .map(|n| format!("{}-{}", slug, n))
+                    .find(|numbered| !used.contains(numbered))
+                    .expect("only finitely many suffixes are in use"),
+                false => slug,
+            };
+            used.insert(slug.clone());
+            format!("{}.csv", slug)
+        })
+        .collect()
+}
+
+/// Reads a grid back as (column, row, value) for every cell that isn't empty.
+fn read_grid(path: &Path) -> Result<Vec<(i64, i64, i64)>, LevelIoError> {
+    let text = std::fs::read_to_string(path)?;
+    let mut cells = Vec::new();
+    for (row, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        for (col, cell) in line.split(',').enumerate() {
+            let cell = cell.trim();
+            if cell.is_empty() {
+                continue;
+            }
+            let value: i64 = cell.parse().map_err(|_| {
+                invalid(format!(
+                    "{} has \"{}\" at row {}, column {}, which isn't a number",
+                    path.display(),
+                    cell,
+                    row + 1,
+                    col + 1
+                ))
+            })?;
+            if value != EMPTY_CELL {
+                cells.push((col as i64, row as i64, value));
+            }
+        }
+    }
+    Ok(cells)
+}
+
+impl Level {
+    fn tile_grid(&self, tiles: &HashMap<HashableVec2, TileIndex>) -> Vec<Vec<i64>> {
+        let bounds = self.bounds();
+        let columns = self.spritesheet_info.num_rows as i64;
+        let mut grid = vec![vec![EMPTY_CELL; bounds.width() as usize]; bounds.height() as usize];
+        for (point, tile) in tiles {
+            let (col, row) = bounds.to_cell(point);
+            grid[row as usize][col as usize] = tile.row as i64 * columns + tile.col as i64;
+        }
+        grid
+    }
+
+    /// Writes a grid per tile layer and int grid, e.g. `level.background.csv`, and
+    /// `level.collision.csv` over the level's bounds, plus the `level.origin.json` sidecar, for a
+    /// chosen `level.csv`.
+    pub fn export_csv(&self, path: &Path) -> Result<(), LevelIoError> {
+        let bounds = self.bounds();
+        check_grid_size(bounds)?;
+        let mut collision = vec![vec![0; bounds.width() as usize]; bounds.height() as usize];
+        let mut collision_shapes = Vec::new();
+        for (point, cell) in sorted_collision(&self.collision) {
+            let (col, row) = bounds.to_cell(point);
+            collision[row as usize][col as usize] = cell.kind as i64 + 1;
+            if !cell.shape.is_full() {
+                collision_shapes.push(CsvCollisionShape {
+                    col,
+                    row,
+                    shape: cell.shape.clone(),
+                });
+            }
+        }
+
+        let file_name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let names = self.layers.iter().map(|layer| layer.name.as_str());
+        let int_grid_names = self.int_grids.iter().map(|layer| layer.name.as_str());
+        let mut layer_paths: Vec<PathBuf> = layer_suffixes(names.chain(int_grid_names))
+            .iter()
+            .map(|suffix| sibling_path(path, suffix))
+            .collect();
+        let int_grid_paths = layer_paths.split_off(self.layers.len());
+        let collision_path = sibling_path(path, "collision.csv");
+        let sidecar = CsvSidecar {
+            format_version: CSV_FORMAT_VERSION,
+            origin_x: bounds.min_x,
+            origin_y: bounds.max_y,
+            width: bounds.width(),
+            height: bounds.height(),
+            columns: self.spritesheet_info.num_rows as u16,
+            layers: self
+                .layers
+                .iter()
+                .zip(&layer_paths)
+                .map(|(layer, path)| CsvLayer {
+                    name: layer.name.clone(),
+                    file: file_name(path),
+                })
+                .collect(),
+            background: String::new(),
+            foreground: String::new(),
+            collision_kinds: self.collision_kinds.clone(),
+            collision: file_name(&collision_path),
+            collision_shapes,
+            int_grids: self
+                .int_grids
+                .iter()
+                .zip(&int_grid_paths)
+                .map(|(layer, path)| CsvIntGrid {
+                    name: layer.name.clone(),
+                    file: file_name(path),
+                    values: layer.values.clone(),
+                })
+                .collect(),
+        };
+        for (layer, path) in self.layers.iter().zip(&layer_paths) {
+            write_grid(path, &self.tile_grid(&layer.tiles))?;
+        }
+        for (layer, path) in self.int_grids.iter().zip(&int_grid_paths) {
+            let mut grid = vec![vec![0; bounds.width() as usize]; bounds.height() as usize];
+            for (point, value) in &layer.cells {
+                let (col, row) = bounds.to_cell(point);
+                grid[row as usize][col as usize] = *value as i64;
+            }
+            write_grid(path, &grid)?;
+        }
+        write_grid(&collision_path, &collision)?;
+        std::fs::write(
+            sibling_path(path, "origin.json"),
+            serde_json::to_vec_pretty(&sidecar)?,
+        )?;
+        Ok(())
+    }
+
+    /// Reads the grids named by a `level.origin.json` sidecar. Entities aren't part of the CSV
+    /// export, so the imported level has none, and `base`'s spritesheet is kept.
+    pub fn import_csv(path: &Path, base: Level) -> Result<Level, LevelIoError> {
+        let sidecar: CsvSidecar = serde_json::from_slice(&std::fs::read(path)?)?;
+        if sidecar.format_version > CSV_FORMAT_VERSION {
+            return Err(LevelIoError::UnsupportedVersion(
+                sidecar.format_version.try_into().unwrap_or(u16::MAX),
+            ));
+        }
+        if sidecar.columns == 0 {
+            return Err(invalid("the sidecar says the spritesheet has no columns"));
+        }
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let to_point = |col: i64, row: i64| {
+            sidecar
+                .origin_x
+                .checked_add(col)
+                .zip(sidecar.origin_y.checked_sub(row))
+                .map(|(x, y)| HashableVec2 { x, y })
+                .ok_or_else(|| invalid("the grids reach past the largest level coordinates"))
+        };
+        let read_tiles = |name: &str| -> Result<HashMap<HashableVec2, TileIndex>, LevelIoError> {
+            let columns = sidecar.columns as i64;
+            read_grid(&dir.join(name))?
+                .into_iter()
+                .map(|(col, row, index)| {
+                    let tile = u16::try_from(index % columns)
+                        .ok()
+                        .zip(u16::try_from(index / columns).ok())
+                        .map(|(col, row)| TileIndex { col, row })
+                        .ok_or_else(|| {
+                            invalid(format!("{} has an invalid tile index {}", name, index))
+                        })?;
+                    Ok((to_point(col, row)?, tile))
+                })
+                .collect()
+        };
+        let layers = if sidecar.format_version < 2 {
+            DEFAULT_LAYER_NAMES
+                .into_iter()
+                .zip([&sidecar.background, &sidecar.foreground])
+                .map(|(name, file)| {
+                    Ok(TileLayer {
+                        name: name.to_string(),
+                        tiles: read_tiles(file)?,
+                    })
+                })
+                .collect::<Result<_, LevelIoError>>()?
+        } else {
+            sidecar
+                .layers
+                .iter()
+                .map(|layer| {
+                    Ok(TileLayer {
+                        name: layer.name.clone(),
+                        tiles: read_tiles(&layer.file)?,
+                    })
+                })
+                .collect::<Result<_, LevelIoError>>()?
+        };
+        let mut collision: HashMap<HashableVec2, CollisionCell> =
+            read_grid(&dir.join(&sidecar.collision))?
+                .into_iter()
+                .filter(|(_, _, value)| *value != 0)
+                .map(|(col, row, value)| {
+                    let kind = u16::try_from(value - 1).map_err(|_| {
+                        invalid(format!(
+                            "{} has an invalid collision value {}",
+                            sidecar.collision, value
+                        ))
+                    })?;
+                    Ok((to_point(col, row)?, CollisionCell::full(kind)))
+                })
+                .collect::<Result<_, LevelIoError>>()?;
+        for shape in sidecar.collision_shapes {
+            let cell = collision
+                .get_mut(&to_point(shape.col, shape.row)?)
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "column {} row {} has a collision shape but no collision",
+                        shape.col, shape.row
+                    ))
+                })?;
+            cell.shape = shape.shape;
+        }
+        let int_grids = sidecar
+            .int_grids
+            .into_iter()
+            .map(|grid| {
+                let cells = read_grid(&dir.join(&grid.file))?
+                    .into_iter()
+                    .filter(|(_, _, value)| *value != 0)
+                    .map(|(col, row, value)| {
+                        let value = u32::try_from(value).map_err(|_| {
+                            invalid(format!("{} has an invalid value {}", grid.file, value))
+                        })?;
+                        Ok((to_point(col, row)?, value))
+                    })
+                    .collect::<Result<_, LevelIoError>>()?;
+                Ok(IntGridLayer {
+                    name: grid.name,
+                    values: grid.values,
+                    cells,
+                })
+            })
+            .collect::<Result<_, LevelIoError>>()?;
+        let level = Level {
+            layers,
+            collision,
+            collision_kinds: sidecar.collision_kinds,
+            int_grids,
+            ..base
+        };
+        level.check_collision()?;
+        level.check_int_grids()?;
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{sample_level, test_dir};
+
+    #[test]
+    fn round_trip() {
+        let dir = test_dir("csv-round-trip");
+        let mut level = sample_level(&dir);
+        let path = dir.join("level.csv");
+        level.export_csv(&path).unwrap();
+        let sidecar = sibling_path(&path, "origin.json");
+        let read = Level::import_csv(&sidecar, level.with_same_spritesheet()).unwrap();
+        // the grids have no room for entities
+        level.entities.clear();
+        assert_eq!(read, level);
+    }
+
+    #[test]
+    fn far_apart_cells_are_refused() {
+        let dir = test_dir("csv-far-apart");
+        let mut level = Level::default();
+        let point = |x, y| HashableVec2 { x, y };
+        level.collision = HashMap::from([
+            (point(0, 0), CollisionCell::full(0)),
+            (point(1 << 40, 1 << 40), CollisionCell::full(0)),
+        ]);
+        assert!(matches!(
+            level.export_csv(&dir.join("level.csv")),
+            Err(LevelIoError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn origin_past_the_largest_coordinates_is_invalid() {
+        let dir = test_dir("csv-origin");
+        let level = sample_level(&dir);
+        let path = dir.join("level.csv");
+        level.export_csv(&path).unwrap();
+        let sidecar = sibling_path(&path, "origin.json");
+        let text = std::fs::read_to_string(&sidecar).unwrap();
+        let origin_x = format!("\"origin_x\": {},", level.bounds().min_x);
+        assert!(text.contains(&origin_x));
+        let text = text.replacen(&origin_x, &format!("\"origin_x\": {},", i64::MAX), 1);
+        std::fs::write(&sidecar, text).unwrap();
+        assert!(matches!(
+            Level::import_csv(&sidecar, Level::default()),
+            Err(LevelIoError::InvalidCsv(_))
+        ));
+    }
+
+    #[test]
+    fn layer_suffixes_never_collide() {
+        let suffixes = layer_suffixes(["a-2", "a", "a", "Collision", "a"].into_iter());
+        assert_eq!(
+            suffixes,
+            ["a-2.csv", "a.csv", "a-3.csv", "collision-2.csv", "a-4.csv"]
+        );
+    }
+}
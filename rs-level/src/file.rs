@@ -0,0 +1,1305 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    is_text_level, CollisionCell, CollisionKind, CollisionShape, HashableVec2, IntGridLayer,
+    IntGridValue, Level, LevelBounds, SpritesheetInfo, SpritesheetSource, TileIndex, TileLayer,
+    DEFAULT_LAYER_NAMES,
+};
+
+/// Every versioned level file starts with these bytes. Files without them predate the header
+/// and are read as format version 0.
+const MAGIC: &[u8; 4] = b"RLVL";
+/// 1 added the header, 2 stores tiles as spritesheet grid indices rather than pixel offsets, 3
+/// gives meaning to the header flags, 4 replaces the background and foreground sections with any
+/// number of named layers in one `TILE` section, 5 gives every collision record a kind, 6 adds
+/// int grid layers, 7 adds collision shapes.
+const FORMAT_VERSION: u16 = 7;
+/// Everything after the header is a zlib stream. Section offsets count from the start of the
+/// file as it is once inflated.
+const FLAG_DEFLATE: u16 = 1 << 0;
+/// Record coordinates are zigzag LEB128 deltas from the previous record's, and tile indices and
+/// label lengths are LEB128 too. Records are sorted by `row_major`, so most points take two bytes.
+const FLAG_VARINT: u16 = 1 << 1;
+const KNOWN_FLAGS: u16 = FLAG_DEFLATE | FLAG_VARINT;
+// magic, version, flags, section count
+const HEADER_LEN: usize = 4 + 2 + 2 + 4;
+// tag, offset from the start of the file, length in bytes
+const SECTION_ENTRY_LEN: usize = 4 + 8 + 8;
+
+/// Before version 4, the layers now named by `DEFAULT_LAYER_NAMES`.
+const SECTION_BACKGROUND: &[u8; 4] = b"BGND";
+const SECTION_FOREGROUND: &[u8; 4] = b"FGND";
+/// For every layer, bottom first: its name, its number of tile records and then the records.
+const SECTION_TILES: &[u8; 4] = b"TILE";
+const SECTION_COLLISION: &[u8; 4] = b"COLL";
+/// For every collision kind: its name and its color as three bytes.
+const SECTION_COLLISION_KINDS: &[u8; 4] = b"CKND";
+/// For every collision cell that isn't full, in `COLL` order: its point, its shape's code and,
+/// for polygons, the number of corners followed by each corner as two f32s.
+const SECTION_COLLISION_SHAPES: &[u8; 4] = b"CSHP";
+/// For every int grid layer: its name, its number of values, each value's number, name and
+/// color, its number of cell records and then the records.
+const SECTION_INT_GRIDS: &[u8; 4] = b"IGRD";
+const SECTION_ENTITY: &[u8; 4] = b"ENTS";
+const SECTION_SPRITESHEET: &[u8; 4] = b"SHET";
+
+const TILE_STRIDE_LEN: usize = 8 + 8 + 2 + 2;
+// before version 5
+const POINT_COLLISION_STRIDE_LEN: usize = 8 + 8;
+const COLLISION_STRIDE_LEN: usize = 8 + 8 + 2;
+/// Shapes are written as their index in `CollisionShape::PRESETS`, or this for polygons.
+const POLYGON_SHAPE_CODE: u8 = 0xff;
+
+/// 64 bit FNV-1a, used to notice when a spritesheet has changed since a level was saved.
+/// Unlike `DefaultHasher` its output is stable across Rust releases, so it can live in files.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Expresses `target` relative to the directory `base`, using `/` as the separator so the result
+/// is portable. Falls back to the absolute path when the two share no common root.
+pub fn relative_path(base: &Path, target: &Path) -> String {
+    let base: Vec<Component> = base.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = base
+        .iter()
+        .zip(target.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return target
+            .iter()
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .into_owned();
+    }
+    let mut parts: Vec<String> = vec!["..".to_string(); base.len() - common];
+    parts.extend(
+        target[common..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// How many previous versions `Level::save` keeps, as `level.lvl.bak1` (the newest) through
+/// `level.lvl.bak3`.
+pub const SAVE_BACKUPS: usize = 3;
+
+/// `level.lvl` becomes `level.lvl.<suffix>`, next to it.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Replaces the file at `path` with `contents` so that it always holds either its old or its new
+/// contents, even if writing is interrupted: the new contents go to a temporary file in the same
+/// directory, are synced to disk and then renamed over `path`. The old file is first copied to
+/// the newest of `backups` rotating `.bakN` files.
+pub fn replace_file(path: &Path, contents: &[u8], backups: usize) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.tmp", name));
+    let write_temp = || {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    };
+    if let Err(why) = write_temp() {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(why);
+    }
+    if backups > 0 && path.exists() {
+        for n in (1..backups).rev() {
+            let older = with_suffix(path, &format!("bak{}", n));
+            if older.exists() {
+                std::fs::rename(&older, with_suffix(path, &format!("bak{}", n + 1)))?;
+            }
+        }
+        std::fs::copy(path, with_suffix(path, "bak1"))?;
+    }
+    std::fs::rename(&temp_path, path)?;
+    // make the rename itself durable, where directories can be synced
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
+/// Lays out the header, the section table and then each section body in order, deflating
+/// everything after the header when `flags` asks for it.
+fn write_sections(sections: &[(&[u8; 4], Vec<u8>)], flags: u16) -> Vec<u8> {
+    let table_len = sections.len() * SECTION_ENTRY_LEN;
+    let body_len: usize = sections.iter().map(|(_, body)| body.len()).sum();
+    let mut buffer: Vec<u8> = Vec::with_capacity(HEADER_LEN + table_len + body_len);
+    buffer.extend_from_slice(MAGIC);
+    buffer.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    buffer.extend_from_slice(&flags.to_le_bytes());
+    buffer.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+    let mut offset = (HEADER_LEN + table_len) as u64;
+    for (tag, body) in sections {
+        buffer.extend_from_slice(*tag);
+        buffer.extend_from_slice(&offset.to_le_bytes());
+        buffer.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        offset += body.len() as u64;
+    }
+    for (_, body) in sections {
+        buffer.extend_from_slice(body);
+    }
+    if flags & FLAG_DEFLATE != 0 {
+        let mut encoder = flate2::write::ZlibEncoder::new(
+            buffer[..HEADER_LEN].to_vec(),
+            flate2::Compression::default(),
+        );
+        // writing into a Vec can't fail
+        encoder.write_all(&buffer[HEADER_LEN..]).unwrap();
+        buffer = encoder.finish().unwrap();
+    }
+    buffer
+}
+
+/// Builds the records of one section, see `SectionReader` for reading them back.
+struct RecordWriter {
+    buffer: Vec<u8>,
+    varint: bool,
+    previous: HashableVec2,
+}
+
+impl RecordWriter {
+    fn new(flags: u16, capacity: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity),
+            varint: flags & FLAG_VARINT != 0,
+            previous: HashableVec2 { x: 0, y: 0 },
+        }
+    }
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buffer.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buffer.push(value as u8);
+    }
+    fn point(&mut self, point: &HashableVec2) {
+        if self.varint {
+            self.varint(zigzag(point.x.wrapping_sub(self.previous.x)));
+            self.varint(zigzag(point.y.wrapping_sub(self.previous.y)));
+            self.previous = *point;
+        } else {
+            self.buffer.extend_from_slice(&point.x.to_le_bytes());
+            self.buffer.extend_from_slice(&point.y.to_le_bytes());
+        }
+    }
+    fn u16(&mut self, value: u16) {
+        if self.varint {
+            self.varint(value as u64);
+        } else {
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    fn u32(&mut self, value: u32) {
+        if self.varint {
+            self.varint(value as u64);
+        } else {
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    /// A length or count, u64 unless records are varints.
+    fn len(&mut self, len: usize) {
+        if self.varint {
+            self.varint(len as u64);
+        } else {
+            self.buffer.extend_from_slice(&(len as u64).to_le_bytes());
+        }
+    }
+    /// A shape's code, followed by the corners of polygons.
+    fn shape(&mut self, shape: &CollisionShape) {
+        match shape {
+            CollisionShape::Polygon(outline) => {
+                self.buffer.push(POLYGON_SHAPE_CODE);
+                self.len(outline.len());
+                for coordinate in outline.iter().flatten() {
+                    self.buffer.extend_from_slice(&coordinate.to_le_bytes());
+                }
+            }
+            shape => {
+                let code = CollisionShape::PRESETS
+                    .iter()
+                    .position(|preset| preset == shape)
+                    .unwrap();
+                self.buffer.push(code as u8);
+            }
+        }
+    }
+    /// Writes the length of `bytes` and then `bytes`.
+    fn bytes_with_len(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.buffer.extend_from_slice(bytes);
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelIoError {
+    Io(std::io::Error),
+    UnsupportedVersion(u16),
+    /// A section, or the header when the tag is `None`, ends before its contents do.
+    TruncatedSection(Option<[u8; 4]>),
+    /// A section's length isn't a whole number of its fixed size records.
+    BadLength([u8; 4], usize),
+    /// A section holds a variable length number that doesn't fit its field.
+    BadVarint([u8; 4]),
+    /// The header sets flags this version doesn't know how to read.
+    UnsupportedFlags(u16),
+    /// A level whose contents parse but can't be used, e.g. a spritesheet split into no tiles.
+    Corrupt(String),
+    /// A collision cell refers to a kind past the end of the level's collision kinds.
+    UnknownCollisionKind(u16),
+    /// A collision shape that is unknown, has too few corners or reaches outside its cell.
+    InvalidCollisionShape(String),
+    /// An int grid layer whose values or cells don't make sense together.
+    InvalidIntGrid(String),
+    InvalidLabelUtf8(std::string::FromUtf8Error),
+    Json(serde_json::Error),
+    /// A Tiled map that is malformed or uses features that can't be imported at all.
+    InvalidTiledMap(String),
+    /// A CSV grid or its sidecar that can't be read back into a level.
+    InvalidCsv(String),
+    /// A text level that doesn't parse, or whose rows can't be read back into layers.
+    InvalidText(String),
+    /// The level stores tiles as pixel offsets but says nothing about the sheet they came from.
+    MissingSpritesheet,
+    /// The level's bounds hold more cells than a format that stores all of them can write.
+    TooLarge(LevelBounds),
+}
+
+impl std::fmt::Display for LevelIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelIoError::Io(why) => write!(f, "{}", why),
+            LevelIoError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            LevelIoError::TruncatedSection(Some(tag)) => write!(
+                f,
+                "the {} section is truncated",
+                String::from_utf8_lossy(tag)
+            ),
+            LevelIoError::TruncatedSection(None) => write!(f, "the header is truncated"),
+            LevelIoError::BadLength(tag, len) => write!(
+                f,
+                "the {} section has an invalid length of {} bytes",
+                String::from_utf8_lossy(tag),
+                len
+            ),
+            LevelIoError::BadVarint(tag) => write!(
+                f,
+                "the {} section has a number that is too large",
+                String::from_utf8_lossy(tag)
+            ),
+            LevelIoError::UnsupportedFlags(flags) => {
+                write!(f, "unsupported format flags {:#06x}", flags)
+            }
+            LevelIoError::Corrupt(why) => write!(f, "{}", why),
+            LevelIoError::UnknownCollisionKind(kind) => write!(
+                f,
+                "a collision cell has kind {}, which the level doesn't define",
+                kind
+            ),
+            LevelIoError::InvalidCollisionShape(why) => write!(f, "{}", why),
+            LevelIoError::InvalidIntGrid(why) => write!(f, "{}", why),
+            LevelIoError::InvalidLabelUtf8(why) => {
+                write!(f, "an entity label is not valid UTF-8: {}", why)
+            }
+            LevelIoError::Json(why) => write!(f, "{}", why),
+            LevelIoError::InvalidTiledMap(why) => write!(f, "{}", why),
+            LevelIoError::InvalidCsv(why) => write!(f, "{}", why),
+            LevelIoError::InvalidText(why) => write!(f, "{}", why),
+            LevelIoError::MissingSpritesheet => write!(
+                f,
+                "this level doesn't record its spritesheet, open the spritesheet first"
+            ),
+            LevelIoError::TooLarge(bounds) => write!(
+                f,
+                "the level spans {}x{} cells, too many for this format",
+                bounds.max_x as i128 - bounds.min_x as i128 + 1,
+                bounds.max_y as i128 - bounds.min_y as i128 + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LevelIoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LevelIoError::Io(why) => Some(why),
+            LevelIoError::InvalidLabelUtf8(why) => Some(why),
+            LevelIoError::Json(why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LevelIoError {
+    fn from(why: std::io::Error) -> Self {
+        LevelIoError::Io(why)
+    }
+}
+
+impl From<serde_json::Error> for LevelIoError {
+    fn from(why: serde_json::Error) -> Self {
+        LevelIoError::Json(why)
+    }
+}
+
+/// Bounds checked little endian reads over one section of a level file.
+struct SectionReader<'a> {
+    tag: Option<[u8; 4]>,
+    bytes: &'a [u8],
+    index: usize,
+    /// Whether records use `FLAG_VARINT`'s encoding, and the last point read if so.
+    varint: bool,
+    previous: HashableVec2,
+}
+
+impl<'a> SectionReader<'a> {
+    fn new(tag: Option<[u8; 4]>, bytes: &'a [u8]) -> Self {
+        Self {
+            tag,
+            bytes,
+            index: 0,
+            varint: false,
+            previous: HashableVec2 { x: 0, y: 0 },
+        }
+    }
+    /// A reader for records written with the header's `flags`.
+    fn with_flags(tag: &[u8; 4], bytes: &'a [u8], flags: u16) -> Self {
+        Self {
+            varint: flags & FLAG_VARINT != 0,
+            ..Self::new(Some(*tag), bytes)
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.index >= self.bytes.len()
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LevelIoError> {
+        let end = self
+            .index
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(LevelIoError::TruncatedSection(self.tag))?;
+        let taken = &self.bytes[self.index..end];
+        self.index = end;
+        Ok(taken)
+    }
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], LevelIoError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    fn u8(&mut self) -> Result<u8, LevelIoError> {
+        Ok(self.take_array::<1>()?[0])
+    }
+    fn u16(&mut self) -> Result<u16, LevelIoError> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+    fn i16(&mut self) -> Result<i16, LevelIoError> {
+        Ok(i16::from_le_bytes(self.take_array()?))
+    }
+    fn u32(&mut self) -> Result<u32, LevelIoError> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+    fn u64(&mut self) -> Result<u64, LevelIoError> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+    fn f32(&mut self) -> Result<f32, LevelIoError> {
+        Ok(f32::from_le_bytes(self.take_array()?))
+    }
+    fn i64(&mut self) -> Result<i64, LevelIoError> {
+        Ok(i64::from_le_bytes(self.take_array()?))
+    }
+    fn varint(&mut self) -> Result<u64, LevelIoError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(LevelIoError::BadVarint(self.tag.unwrap_or_default()))
+    }
+    fn point(&mut self) -> Result<HashableVec2, LevelIoError> {
+        if !self.varint {
+            let x = self.i64()?;
+            let y = self.i64()?;
+            return Ok(HashableVec2 { x, y });
+        }
+        let x = self.previous.x.wrapping_add(unzigzag(self.varint()?));
+        let y = self.previous.y.wrapping_add(unzigzag(self.varint()?));
+        self.previous = HashableVec2 { x, y };
+        Ok(self.previous)
+    }
+    /// A u16 field of a record, see `RecordWriter::u16`.
+    fn record_u16(&mut self) -> Result<u16, LevelIoError> {
+        if !self.varint {
+            return self.u16();
+        }
+        u16::try_from(self.varint()?)
+            .map_err(|_| LevelIoError::BadVarint(self.tag.unwrap_or_default()))
+    }
+    /// A u32 field of a record, see `RecordWriter::u32`.
+    fn record_u32(&mut self) -> Result<u32, LevelIoError> {
+        if !self.varint {
+            return self.u32();
+        }
+        u32::try_from(self.varint()?)
+            .map_err(|_| LevelIoError::BadVarint(self.tag.unwrap_or_default()))
+    }
+    /// A length or count, see `RecordWriter::len`.
+    fn len(&mut self) -> Result<usize, LevelIoError> {
+        let len = match self.varint {
+            true => self.varint()?,
+            false => self.u64()?,
+        };
+        usize::try_from(len).map_err(|_| LevelIoError::TruncatedSection(self.tag))
+    }
+    /// Reads a length and then that many bytes.
+    fn bytes_with_len(&mut self) -> Result<&'a [u8], LevelIoError> {
+        let len = self.len()?;
+        self.take(len)
+    }
+    fn string(&mut self) -> Result<String, LevelIoError> {
+        String::from_utf8(self.bytes_with_len()?.into()).map_err(LevelIoError::InvalidLabelUtf8)
+    }
+    /// Checks that the section splits evenly into records of `stride` bytes. Varint records have
+    /// no fixed size, so they always pass.
+    fn expect_stride(&self, stride: usize) -> Result<(), LevelIoError> {
+        if !self.varint && !self.bytes.len().is_multiple_of(stride) {
+            return Err(LevelIoError::BadLength(
+                self.tag.unwrap_or_default(),
+                self.bytes.len(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Sheet size and grid used to turn the pixel offsets stored by versions 0 and 1 into tile
+/// indices.
+struct PixelGrid {
+    width: f32,
+    height: f32,
+    tiles_across: u8,
+    tiles_down: u8,
+}
+
+impl PixelGrid {
+    fn of(level: &Level) -> Option<PixelGrid> {
+        level.spritesheet.as_ref().map(|source| PixelGrid {
+            width: source.width as f32,
+            height: source.height as f32,
+            tiles_across: level.spritesheet_info.num_rows,
+            tiles_down: level.spritesheet_info.num_cols,
+        })
+    }
+}
+
+fn read_tile_record(
+    reader: &mut SectionReader,
+    tiles: &mut HashMap<HashableVec2, TileIndex>,
+) -> Result<(), LevelIoError> {
+    let point = reader.point()?;
+    let col = reader.record_u16()?;
+    let row = reader.record_u16()?;
+    tiles.insert(point, TileIndex { col, row });
+    Ok(())
+}
+
+/// The BGND and FGND sections of versions 2 and 3.
+fn read_tile_records(
+    tag: &[u8; 4],
+    bytes: &[u8],
+    flags: u16,
+) -> Result<HashMap<HashableVec2, TileIndex>, LevelIoError> {
+    let mut reader = SectionReader::with_flags(tag, bytes, flags);
+    reader.expect_stride(TILE_STRIDE_LEN)?;
+    let mut tiles = HashMap::with_capacity(bytes.len() / TILE_STRIDE_LEN);
+    while !reader.is_empty() {
+        read_tile_record(&mut reader, &mut tiles)?;
+    }
+    Ok(tiles)
+}
+
+fn read_layer_records(bytes: &[u8], flags: u16) -> Result<Vec<TileLayer>, LevelIoError> {
+    let mut reader = SectionReader::with_flags(SECTION_TILES, bytes, flags);
+    let mut layers = Vec::new();
+    while !reader.is_empty() {
+        let mut layer = TileLayer::new(reader.string()?);
+        for _ in 0..reader.len()? {
+            read_tile_record(&mut reader, &mut layer.tiles)?;
+        }
+        layers.push(layer);
+    }
+    Ok(layers)
+}
+
+/// Versions 0 and 1 stored the pixel offset of each tile's top left corner on the sheet.
+fn read_pixel_tile_records(
+    tag: &[u8; 4],
+    bytes: &[u8],
+    grid: Option<&PixelGrid>,
+) -> Result<HashMap<HashableVec2, TileIndex>, LevelIoError> {
+    let mut reader = SectionReader::new(Some(*tag), bytes);
+    reader.expect_stride(TILE_STRIDE_LEN)?;
+    if reader.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let grid = grid.ok_or(LevelIoError::MissingSpritesheet)?;
+    let tile_width = grid.width / grid.tiles_across as f32;
+    let tile_height = grid.height / grid.tiles_down as f32;
+    let mut tiles = HashMap::with_capacity(bytes.len() / TILE_STRIDE_LEN);
+    while !reader.is_empty() {
+        let x = reader.i64()?;
+        let y = reader.i64()?;
+        let pixel_x = reader.i16()?;
+        let pixel_y = reader.i16()?;
+        let tile = TileIndex {
+            col: (pixel_x as f32 / tile_width).round() as u16,
+            row: (pixel_y as f32 / tile_height).round() as u16,
+        };
+        tiles.insert(HashableVec2 { x, y }, tile);
+    }
+    Ok(tiles)
+}
+
+/// Before version 5 collision records have no kind, and are all the first kind. Every cell is
+/// full until `read_collision_shape_records`.
+fn read_collision_records(
+    bytes: &[u8],
+    flags: u16,
+    with_kinds: bool,
+) -> Result<HashMap<HashableVec2, CollisionCell>, LevelIoError> {
+    let mut reader = SectionReader::with_flags(SECTION_COLLISION, bytes, flags);
+    reader.expect_stride(match with_kinds {
+        true => COLLISION_STRIDE_LEN,
+        false => POINT_COLLISION_STRIDE_LEN,
+    })?;
+    let mut collision = HashMap::new();
+    while !reader.is_empty() {
+        let point = reader.point()?;
+        let kind = match with_kinds {
+            true => reader.record_u16()?,
+            false => 0,
+        };
+        collision.insert(point, CollisionCell::full(kind));
+    }
+    Ok(collision)
+}
+
+fn read_collision_shape_records(
+    bytes: &[u8],
+    flags: u16,
+    collision: &mut HashMap<HashableVec2, CollisionCell>,
+) -> Result<(), LevelIoError> {
+    let mut reader = SectionReader::with_flags(SECTION_COLLISION_SHAPES, bytes, flags);
+    while !reader.is_empty() {
+        let point = reader.point()?;
+        let code = reader.u8()?;
+        let shape = match code {
+            POLYGON_SHAPE_CODE => {
+                let mut outline = Vec::new();
+                for _ in 0..reader.len()? {
+                    outline.push([reader.f32()?, reader.f32()?]);
+                }
+                CollisionShape::Polygon(outline)
+            }
+            code => CollisionShape::PRESETS
+                .get(code as usize)
+                .cloned()
+                .ok_or_else(|| {
+                    LevelIoError::InvalidCollisionShape(format!("unknown collision shape {}", code))
+                })?,
+        };
+        let cell = collision.get_mut(&point).ok_or_else(|| {
+            LevelIoError::InvalidCollisionShape(format!(
+                "({}, {}) has a collision shape but no collision",
+                point.x, point.y
+            ))
+        })?;
+        cell.shape = shape;
+    }
+    Ok(())
+}
+
+fn read_collision_kind_records(bytes: &[u8]) -> Result<Vec<CollisionKind>, LevelIoError> {
+    let mut reader = SectionReader::new(Some(*SECTION_COLLISION_KINDS), bytes);
+    let mut kinds = Vec::new();
+    while !reader.is_empty() {
+        let name = reader.string()?;
+        let color = reader.take_array()?;
+        kinds.push(CollisionKind { name, color });
+    }
+    Ok(kinds)
+}
+
+fn read_int_grid_records(bytes: &[u8], flags: u16) -> Result<Vec<IntGridLayer>, LevelIoError> {
+    let mut reader = SectionReader::with_flags(SECTION_INT_GRIDS, bytes, flags);
+    let mut layers = Vec::new();
+    while !reader.is_empty() {
+        let mut layer = IntGridLayer::new(reader.string()?);
+        for _ in 0..reader.len()? {
+            let value = reader.record_u32()?;
+            let name = reader.string()?;
+            let color = reader.take_array()?;
+            layer.values.push(IntGridValue { value, name, color });
+        }
+        for _ in 0..reader.len()? {
+            let point = reader.point()?;
+            let value = reader.record_u32()?;
+            layer.cells.insert(point, value);
+        }
+        layers.push(layer);
+    }
+    Ok(layers)
+}
+
+fn read_entity_records(
+    bytes: &[u8],
+    flags: u16,
+) -> Result<HashMap<HashableVec2, String>, LevelIoError> {
+    let mut reader = SectionReader::with_flags(SECTION_ENTITY, bytes, flags);
+    let mut entities = HashMap::new();
+    while !reader.is_empty() {
+        let point = reader.point()?;
+        let label = reader.string()?;
+        entities.insert(point, label);
+    }
+    Ok(entities)
+}
+
+/// Rejects a spritesheet split into no tiles, which `Level::tile_pixel_size` would divide by.
+pub(crate) fn check_spritesheet_info(info: &SpritesheetInfo) -> Result<(), LevelIoError> {
+    if info.num_rows == 0 || info.num_cols == 0 {
+        return Err(LevelIoError::Corrupt(format!(
+            "the spritesheet is split into {}x{} tiles",
+            info.num_rows, info.num_cols
+        )));
+    }
+    Ok(())
+}
+
+/// Grids of more cells than this, 2048x2048, are refused by the formats that store every cell of
+/// the level's bounds rather than allocated.
+pub(crate) const MAX_GRID_CELLS: u64 = 1 << 22;
+
+/// Rejects bounds too large to write as a grid, see `MAX_GRID_CELLS`.
+pub(crate) fn check_grid_size(bounds: LevelBounds) -> Result<(), LevelIoError> {
+    match bounds.cell_count() {
+        Some(cells) if cells <= MAX_GRID_CELLS => Ok(()),
+        _ => Err(LevelIoError::TooLarge(bounds)),
+    }
+}
+
+/// The recorded path is relative to the level, so `level_dir` is needed to resolve it.
+fn read_spritesheet_record(
+    bytes: &[u8],
+    level_dir: &Path,
+) -> Result<(SpritesheetInfo, SpritesheetSource), LevelIoError> {
+    let mut reader = SectionReader::new(Some(*SECTION_SPRITESHEET), bytes);
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let info = SpritesheetInfo {
+        sprite_size: reader.u16()?,
+        num_rows: reader.u8()?,
+        num_cols: reader.u8()?,
+    };
+    check_spritesheet_info(&info)?;
+    let hash = reader.u64()?;
+    let relative_path = reader.string()?;
+    let source = SpritesheetSource {
+        path: level_dir.join(relative_path),
+        width,
+        height,
+        // 0 stands for a sheet whose hash was never known, see `write_spritesheet_record`
+        hash: (hash != 0).then_some(hash),
+    };
+    Ok((info, source))
+}
+
+/// Headerless layout written before the format was versioned: three u64 section lengths
+/// followed by background, foreground, collision and then entity records until EOF.
+fn read_v0(buf: &[u8], base: Level) -> Result<Level, LevelIoError> {
+    // versions that skipped saving levels without background tiles left empty files behind
+    if buf.is_empty() {
+        return Ok(base);
+    }
+    let grid = PixelGrid::of(&base);
+    let mut reader = SectionReader::new(None, buf);
+    let len_bg = reader.u64()? as usize;
+    let len_fg = reader.u64()? as usize;
+    let len_collision = reader.u64()? as usize;
+    let mut take_section = |tag: &[u8; 4], len: usize| {
+        reader
+            .take(len)
+            .map_err(|_| LevelIoError::TruncatedSection(Some(*tag)))
+    };
+    let background_bytes = take_section(SECTION_BACKGROUND, len_bg)?;
+    let foreground_bytes = take_section(SECTION_FOREGROUND, len_fg)?;
+    let collision_bytes = take_section(SECTION_COLLISION, len_collision)?;
+    let [background, foreground] = DEFAULT_LAYER_NAMES;
+    Ok(Level {
+        layers: vec![
+            TileLayer {
+                name: background.to_string(),
+                tiles: read_pixel_tile_records(
+                    SECTION_BACKGROUND,
+                    background_bytes,
+                    grid.as_ref(),
+                )?,
+            },
+            TileLayer {
+                name: foreground.to_string(),
+                tiles: read_pixel_tile_records(
+                    SECTION_FOREGROUND,
+                    foreground_bytes,
+                    grid.as_ref(),
+                )?,
+            },
+        ],
+        collision: read_collision_records(collision_bytes, 0, false)?,
+        entities: read_entity_records(&buf[reader.index..], 0)?,
+        ..base
+    })
+}
+
+/// Returns the body of every section in the table, keyed by tag. Unknown tags are kept so that
+/// callers can ignore sections added by newer versions.
+fn read_sections(buf: &[u8]) -> Result<HashMap<[u8; 4], &[u8]>, LevelIoError> {
+    let mut header = SectionReader::new(None, buf);
+    header.take(4 + 2 + 2)?;
+    let section_count = header.u32()? as usize;
+    let mut sections = HashMap::new();
+    let mut ranges = Vec::new();
+    for _ in 0..section_count {
+        let tag = header.take_array::<4>()?;
+        let offset = header.u64()? as usize;
+        let len = header.u64()? as usize;
+        let body = offset
+            .checked_add(len)
+            .and_then(|end| buf.get(offset..end))
+            .ok_or(LevelIoError::TruncatedSection(Some(tag)))?;
+        sections.insert(tag, body);
+        if len > 0 {
+            ranges.push((offset, offset + len, tag));
+        }
+    }
+    // bodies come after the table and each has bytes of its own, anything else is a damaged file
+    let table_end = HEADER_LEN + section_count * SECTION_ENTRY_LEN;
+    ranges.sort_unstable();
+    let mut previous_end = table_end;
+    for (start, end, tag) in ranges {
+        if start < previous_end {
+            return Err(LevelIoError::Corrupt(format!(
+                "section {} overlaps the section table or another section",
+                String::from_utf8_lossy(&tag)
+            )));
+        }
+        previous_end = end;
+    }
+    Ok(sections)
+}
+
+/// Undoes `FLAG_DEFLATE`, returning the file as it was before it was compressed.
+fn inflate(buf: &[u8]) -> Result<Vec<u8>, LevelIoError> {
+    let header = buf
+        .get(..HEADER_LEN)
+        .ok_or(LevelIoError::TruncatedSection(None))?;
+    let mut inflated = header.to_vec();
+    flate2::read::ZlibDecoder::new(&buf[HEADER_LEN..]).read_to_end(&mut inflated)?;
+    Ok(inflated)
+}
+
+/// Versions 1 through 7 share a layout. Version 1 stores tiles as pixel offsets and later
+/// versions as tile indices, only versions 3 and up set `flags`, version 4 keeps its layers in
+/// `SECTION_TILES` rather than a section each for the background and foreground, and version 5
+/// adds collision kinds. Versions 6 and 7 add int grids and collision shapes, which older
+/// versions simply don't have.
+fn read_sectioned(
+    buf: &[u8],
+    version: u16,
+    flags: u16,
+    level_dir: &Path,
+    mut level: Level,
+) -> Result<Level, LevelIoError> {
+    let inflated;
+    let buf = if flags & FLAG_DEFLATE != 0 {
+        inflated = inflate(buf)?;
+        &inflated[..]
+    } else {
+        buf
+    };
+    level.compressed = flags & FLAG_DEFLATE != 0;
+    let sections = read_sections(buf)?;
+    if let Some(bytes) = sections.get(SECTION_SPRITESHEET) {
+        let (info, source) = read_spritesheet_record(bytes, level_dir)?;
+        level.spritesheet_info = info;
+        level.spritesheet = Some(source);
+    }
+    let grid = PixelGrid::of(&level);
+    if version >= 4 {
+        level.layers = match sections.get(SECTION_TILES) {
+            Some(bytes) => read_layer_records(bytes, flags)?,
+            None => Vec::new(),
+        };
+    } else {
+        level.layers = DEFAULT_LAYER_NAMES
+            .into_iter()
+            .map(TileLayer::new)
+            .collect();
+        for (tag, layer) in [SECTION_BACKGROUND, SECTION_FOREGROUND]
+            .into_iter()
+            .zip(&mut level.layers)
+        {
+            if let Some(bytes) = sections.get(tag) {
+                layer.tiles = match version {
+                    1 => read_pixel_tile_records(tag, bytes, grid.as_ref())?,
+                    _ => read_tile_records(tag, bytes, flags)?,
+                };
+            }
+        }
+    }
+    if let Some(bytes) = sections.get(SECTION_COLLISION_KINDS) {
+        level.collision_kinds = read_collision_kind_records(bytes)?;
+    }
+    if let Some(bytes) = sections.get(SECTION_COLLISION) {
+        level.collision = read_collision_records(bytes, flags, version >= 5)?;
+    }
+    if let Some(bytes) = sections.get(SECTION_COLLISION_SHAPES) {
+        read_collision_shape_records(bytes, flags, &mut level.collision)?;
+    }
+    level.check_collision()?;
+    if let Some(bytes) = sections.get(SECTION_INT_GRIDS) {
+        level.int_grids = read_int_grid_records(bytes, flags)?;
+    }
+    level.check_int_grids()?;
+    if let Some(bytes) = sections.get(SECTION_ENTITY) {
+        level.entities = read_entity_records(bytes, flags)?;
+    }
+    Ok(level)
+}
+
+/// Orders points by y then x. Records are written in this order so saving an unchanged level
+/// reproduces the same bytes and small edits make small diffs.
+fn row_major(point: &HashableVec2) -> (i64, i64) {
+    (point.y, point.x)
+}
+
+/// Tiles in the order they're written, see `row_major`.
+pub(crate) fn sorted_tiles(
+    tiles: &HashMap<HashableVec2, TileIndex>,
+) -> Vec<(&HashableVec2, &TileIndex)> {
+    let mut tiles: Vec<_> = tiles.iter().collect();
+    tiles.sort_unstable_by_key(|(point, _)| row_major(point));
+    tiles
+}
+
+/// Collision cells in the order they're written, see `row_major`.
+pub(crate) fn sorted_collision(
+    collision: &HashMap<HashableVec2, CollisionCell>,
+) -> Vec<(&HashableVec2, &CollisionCell)> {
+    let mut collision: Vec<_> = collision.iter().collect();
+    collision.sort_unstable_by_key(|(point, _)| row_major(point));
+    collision
+}
+
+/// Int grid cells in the order they're written, see `row_major`.
+pub(crate) fn sorted_int_grid_cells(
+    cells: &HashMap<HashableVec2, u32>,
+) -> Vec<(&HashableVec2, &u32)> {
+    let mut cells: Vec<_> = cells.iter().collect();
+    cells.sort_unstable_by_key(|(point, _)| row_major(point));
+    cells
+}
+
+/// Entities in the order they're written: by label, then by position.
+pub(crate) fn sorted_entities(
+    entities: &HashMap<HashableVec2, String>,
+) -> Vec<(&HashableVec2, &String)> {
+    let mut entities: Vec<_> = entities.iter().collect();
+    entities.sort_unstable_by(|(a, a_label), (b, b_label)| {
+        a_label.cmp(b_label).then(row_major(a).cmp(&row_major(b)))
+    });
+    entities
+}
+
+fn write_layer_records(layers: &[TileLayer], flags: u16) -> Vec<u8> {
+    let tile_count: usize = layers.iter().map(|layer| layer.tiles.len()).sum();
+    // names are usually short
+    let mut records = RecordWriter::new(flags, tile_count * TILE_STRIDE_LEN + layers.len() * 32);
+    for layer in layers {
+        records.bytes_with_len(layer.name.as_bytes());
+        records.len(layer.tiles.len());
+        for (point, tile) in sorted_tiles(&layer.tiles) {
+            records.point(point);
+            records.u16(tile.col);
+            records.u16(tile.row);
+        }
+    }
+    records.buffer
+}
+
+fn write_int_grid_records(layers: &[IntGridLayer], flags: u16) -> Vec<u8> {
+    let cell_count: usize = layers.iter().map(|layer| layer.cells.len()).sum();
+    let mut records = RecordWriter::new(flags, cell_count * (8 + 8 + 4) + layers.len() * 64);
+    for layer in layers {
+        records.bytes_with_len(layer.name.as_bytes());
+        records.len(layer.values.len());
+        for value in &layer.values {
+            records.u32(value.value);
+            records.bytes_with_len(value.name.as_bytes());
+            records.buffer.extend_from_slice(&value.color);
+        }
+        records.len(layer.cells.len());
+        for (point, value) in sorted_int_grid_cells(&layer.cells) {
+            records.point(point);
+            records.u32(*value);
+        }
+    }
+    records.buffer
+}
+
+impl Level {
+    /// Checks that every collision cell's kind is one of `collision_kinds`, and that every
+    /// polygon has at least three corners, all inside its cell. Readers call this before
+    /// returning a level, so the editor and exporters can index the palette freely.
+    pub(crate) fn check_collision(&self) -> Result<(), LevelIoError> {
+        if let Some(kind) = self.collision.values().map(|cell| cell.kind).max() {
+            if kind as usize >= self.collision_kinds.len() {
+                return Err(LevelIoError::UnknownCollisionKind(kind));
+            }
+        }
+        for (point, cell) in &self.collision {
+            if let CollisionShape::Polygon(outline) = &cell.shape {
+                let inside = outline
+                    .iter()
+                    .flatten()
+                    .all(|coordinate| (0.0..=1.0).contains(coordinate));
+                if outline.len() < 3 || !inside {
+                    return Err(LevelIoError::InvalidCollisionShape(format!(
+                        "the collision polygon at ({}, {}) needs at least 3 corners, each \
+                         between 0 and 1",
+                        point.x, point.y
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every int grid layer defines each of its values once, none of them 0, and
+    /// that its cells only hold those values. Readers call this alongside `check_collision`.
+    pub(crate) fn check_int_grids(&self) -> Result<(), LevelIoError> {
+        for layer in &self.int_grids {
+            for (i, value) in layer.values.iter().enumerate() {
+                if value.value == 0 {
+                    return Err(LevelIoError::InvalidIntGrid(format!(
+                        "int grid \"{}\" defines the value 0, which stands for an empty cell",
+                        layer.name
+                    )));
+                }
+                if layer.values[..i]
+                    .iter()
+                    .any(|other| other.value == value.value)
+                {
+                    return Err(LevelIoError::InvalidIntGrid(format!(
+                        "int grid \"{}\" defines the value {} more than once",
+                        layer.name, value.value
+                    )));
+                }
+            }
+            if let Some(value) = layer
+                .cells
+                .values()
+                .find(|value| layer.value(**value).is_none())
+            {
+                return Err(LevelIoError::InvalidIntGrid(format!(
+                    "a cell of int grid \"{}\" holds {}, which the layer doesn't define",
+                    layer.name, value
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a .lvl file read from `level_path`, which relative spritesheet paths are resolved
+    /// against. The result starts from `base`, usually `Level::with_same_spritesheet`, so files
+    /// that don't record a spritesheet keep its, and older files that stored pixel offsets are
+    /// converted with its grid.
+    pub fn read(buf: &[u8], level_path: &Path, base: Level) -> Result<Level, LevelIoError> {
+        if !buf.starts_with(MAGIC) {
+            return read_v0(buf, base);
+        }
+        let level_dir = level_path.parent().unwrap_or_else(|| Path::new(""));
+        let mut header = SectionReader::new(None, &buf[4..]);
+        let version = header.u16()?;
+        let flags = header.u16()?;
+        match version {
+            // flags were reserved before version 3 and always written as 0
+            1 | 2 => read_sectioned(buf, version, 0, level_dir, base),
+            3..=7 if flags & !KNOWN_FLAGS != 0 => Err(LevelIoError::UnsupportedFlags(flags)),
+            3..=7 => read_sectioned(buf, version, flags, level_dir, base),
+            _ => Err(LevelIoError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Reads the level at `path`, see `Level::read`, or `Level::read_text` for paths ending in
+    /// `TEXT_LEVEL_EXTENSION`.
+    pub fn open(path: &Path, base: Level) -> Result<Level, LevelIoError> {
+        if is_text_level(path) {
+            let text = std::fs::read_to_string(path)?;
+            return Level::read_text(&text, path, base);
+        }
+        let buf = std::fs::read(path)?;
+        Level::read(&buf, path, base)
+    }
+
+    fn write_spritesheet_record(&self, source: &SpritesheetSource, level_path: &Path) -> Vec<u8> {
+        let level_dir = level_path.parent().unwrap_or_else(|| Path::new(""));
+        let relative = relative_path(level_dir, &source.path);
+        let mut buffer = Vec::with_capacity(4 + 4 + 2 + 1 + 1 + 8 + 8 + relative.len());
+        buffer.extend_from_slice(&source.width.to_le_bytes());
+        buffer.extend_from_slice(&source.height.to_le_bytes());
+        buffer.extend_from_slice(&self.spritesheet_info.sprite_size.to_le_bytes());
+        buffer.push(self.spritesheet_info.num_rows);
+        buffer.push(self.spritesheet_info.num_cols);
+        // sheets imported from formats without a hash are written as 0
+        buffer.extend_from_slice(&source.hash.unwrap_or_default().to_le_bytes());
+        buffer.extend_from_slice(&(relative.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(relative.as_bytes());
+        buffer
+    }
+
+    /// Serializes the level as it would be saved to `level_path`, which the spritesheet path is
+    /// recorded relative to. Compressed levels are deflated and use varint records.
+    pub fn write(&self, level_path: &Path) -> Vec<u8> {
+        let flags = match self.compressed {
+            true => FLAG_DEFLATE | FLAG_VARINT,
+            false => 0,
+        };
+        let layers = write_layer_records(&self.layers, flags);
+        let mut collision = RecordWriter::new(flags, self.collision.len() * COLLISION_STRIDE_LEN);
+        let mut collision_shapes = RecordWriter::new(flags, 0);
+        for (point, cell) in sorted_collision(&self.collision) {
+            collision.point(point);
+            collision.u16(cell.kind);
+            if !cell.shape.is_full() {
+                collision_shapes.point(point);
+                collision_shapes.shape(&cell.shape);
+            }
+        }
+        let mut collision_kinds = Vec::new();
+        for kind in &self.collision_kinds {
+            collision_kinds.extend_from_slice(&(kind.name.len() as u64).to_le_bytes());
+            collision_kinds.extend_from_slice(kind.name.as_bytes());
+            collision_kinds.extend_from_slice(&kind.color);
+        }
+        let int_grids = write_int_grid_records(&self.int_grids, flags);
+        // we don't know label length
+        let mut entity = RecordWriter::new(flags, self.entities.len() * 64);
+        for (point, label) in sorted_entities(&self.entities) {
+            entity.point(point);
+            entity.bytes_with_len(label.as_bytes());
+        }
+        let mut sections = vec![
+            (SECTION_TILES, layers),
+            (SECTION_COLLISION, collision.buffer),
+            (SECTION_COLLISION_KINDS, collision_kinds),
+            (SECTION_COLLISION_SHAPES, collision_shapes.buffer),
+            (SECTION_INT_GRIDS, int_grids),
+            (SECTION_ENTITY, entity.buffer),
+        ];
+        if let Some(source) = &self.spritesheet {
+            sections.push((
+                SECTION_SPRITESHEET,
+                self.write_spritesheet_record(source, level_path),
+            ));
+        }
+        write_sections(&sections, flags)
+    }
+
+    /// Saves in the text format for paths ending in `TEXT_LEVEL_EXTENSION`, otherwise in the
+    /// binary one. The file is replaced atomically and its previous versions kept, see
+    /// `replace_file`.
+    pub fn save(&self, path: &Path) -> Result<(), LevelIoError> {
+        let buffer = if is_text_level(path) {
+            self.write_text(path)?.into_bytes()
+        } else {
+            self.write(path)
+        };
+        replace_file(path, &buffer, SAVE_BACKUPS)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{reinserted, sample_level, test_dir};
+
+    #[test]
+    fn round_trip() {
+        let dir = test_dir("file-round-trip");
+        let level = sample_level(&dir);
+        let level_path = dir.join("level.lvl");
+        let buf = level.write(&level_path);
+        assert!(buf.starts_with(MAGIC));
+        let read = Level::read(&buf, &level_path, Level::default()).unwrap();
+        assert_eq!(read, level);
+    }
+
+    #[test]
+    fn writes_are_deterministic() {
+        let dir = test_dir("file-deterministic");
+        let level = sample_level(&dir);
+        let level_path = dir.join("level.lvl");
+        let buf = level.write(&level_path);
+        assert_eq!(level.write(&level_path), buf);
+        assert_eq!(reinserted(&level).write(&level_path), buf);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let dir = test_dir("file-compressed");
+        let mut level = sample_level(&dir);
+        level.compressed = true;
+        let level_path = dir.join("level.lvl");
+        let buf = level.write(&level_path);
+        assert_eq!(level.write(&level_path), buf);
+        assert_eq!(reinserted(&level).write(&level_path), buf);
+        let read = Level::read(&buf, &level_path, Level::default()).unwrap();
+        assert_eq!(read, level);
+        for len in MAGIC.len()..buf.len() {
+            assert!(Level::read(&buf[..len], &level_path, Level::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn replace_file_rotates_backups() {
+        let dir = test_dir("file-backups");
+        let path = dir.join("level.lvl");
+        for version in 0..5u8 {
+            replace_file(&path, &[version], 3).unwrap();
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), [4]);
+        for (n, version) in [(1, 3), (2, 2), (3, 1)] {
+            let backup = with_suffix(&path, &format!("bak{}", n));
+            assert_eq!(std::fs::read(backup).unwrap(), [version]);
+        }
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "level.lvl",
+                "level.lvl.bak1",
+                "level.lvl.bak2",
+                "level.lvl.bak3"
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let dir = test_dir("file-bad-magic");
+        let level_path = dir.join("level.lvl");
+        let mut buf = sample_level(&dir).write(&level_path);
+        buf[0] = b'X';
+        assert!(Level::read(&buf, &level_path, Level::default()).is_err());
+    }
+
+    #[test]
+    fn newer_version_is_unsupported() {
+        let dir = test_dir("file-newer-version");
+        let level_path = dir.join("level.lvl");
+        let mut buf = sample_level(&dir).write(&level_path);
+        buf[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Level::read(&buf, &level_path, Level::default()),
+            Err(LevelIoError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let dir = test_dir("file-truncated");
+        let level_path = dir.join("level.lvl");
+        let buf = sample_level(&dir).write(&level_path);
+        for len in MAGIC.len()..buf.len() {
+            assert!(
+                Level::read(&buf[..len], &level_path, Level::default()).is_err(),
+                "read {} of {} bytes",
+                len,
+                buf.len()
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_section_table_is_an_error() {
+        let buf = write_sections(&[(b"AAAA", vec![1; 8]), (b"BBBB", vec![2; 8])], 0);
+        let cut = &buf[..HEADER_LEN + 6];
+        assert!(matches!(
+            read_sections(cut),
+            Err(LevelIoError::TruncatedSection(None))
+        ));
+    }
+
+    #[test]
+    fn empty_spritesheet_grid_is_corrupt() {
+        let dir = test_dir("file-empty-grid");
+        let level_path = dir.join("level.lvl");
+        let mut level = sample_level(&dir);
+        level.spritesheet_info.num_rows = 0;
+        let buf = level.write(&level_path);
+        assert!(matches!(
+            Level::read(&buf, &level_path, Level::default()),
+            Err(LevelIoError::Corrupt(_))
+        ));
+    }
+
+    /// Points the offset of section `index` at `offset`.
+    fn move_section(buf: &mut [u8], index: usize, offset: u64) {
+        let entry = HEADER_LEN + index * SECTION_ENTRY_LEN + 4;
+        buf[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    #[test]
+    fn overlapping_sections_are_errors() {
+        let mut buf = write_sections(&[(b"AAAA", vec![1; 8]), (b"BBBB", vec![2; 8])], 0);
+        assert!(read_sections(&buf).is_ok());
+        let first_body = (HEADER_LEN + 2 * SECTION_ENTRY_LEN) as u64;
+        move_section(&mut buf, 1, first_body + 4);
+        assert!(matches!(read_sections(&buf), Err(LevelIoError::Corrupt(_))));
+        move_section(&mut buf, 1, HEADER_LEN as u64);
+        assert!(matches!(read_sections(&buf), Err(LevelIoError::Corrupt(_))));
+    }
+}
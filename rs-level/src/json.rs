@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::file::{
+    check_spritesheet_info, relative_path, sorted_collision, sorted_entities,
+    sorted_int_grid_cells, sorted_tiles, LevelIoError,
+};
+use crate::{
+    default_collision_kinds, CollisionCell, CollisionKind, CollisionShape, HashableVec2,
+    IntGridLayer, IntGridValue, Level, SpritesheetInfo, SpritesheetSource, TileIndex, TileLayer,
+    DEFAULT_LAYER_NAMES,
+};
+
+/// 2 replaced `background` and `foreground` with a list of named `layers`, 3 gave collision
+/// records a `kind`, 4 added `int_grids`, 5 gave collision records a `shape`.
+const JSON_FORMAT_VERSION: u32 = 5;
+
+/// JSON objects can only have string keys, so coordinate keyed layers are written as lists of
+/// flat `{x, y, col, row}` records instead, in the same order as the binary format's records.
+mod tile_layer {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        #[serde(flatten)]
+        point: HashableVec2,
+        #[serde(flatten)]
+        tile: TileIndex,
+    }
+
+    pub fn serialize<S: Serializer>(
+        tiles: &HashMap<HashableVec2, TileIndex>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(sorted_tiles(tiles).into_iter().map(|(point, tile)| Record {
+            point: *point,
+            tile: *tile,
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<HashableVec2, TileIndex>, D::Error> {
+        let records = Vec::<Record>::deserialize(deserializer)?;
+        Ok(records
+            .into_iter()
+            .map(|record| (record.point, record.tile))
+            .collect())
+    }
+}
+
+/// Collision cells are written as `{x, y, kind, shape}` records, see `tile_layer`, leaving out the
+/// shape of full cells. Version 2 records have no kind and are all the first one.
+mod collision_layer {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        #[serde(flatten)]
+        point: HashableVec2,
+        #[serde(default)]
+        kind: u16,
+        #[serde(default, skip_serializing_if = "CollisionShape::is_full")]
+        shape: CollisionShape,
+    }
+
+    pub fn serialize<S: Serializer>(
+        collision: &HashMap<HashableVec2, CollisionCell>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            sorted_collision(collision)
+                .into_iter()
+                .map(|(point, cell)| Record {
+                    point: *point,
+                    kind: cell.kind,
+                    shape: cell.shape.clone(),
+                }),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<HashableVec2, CollisionCell>, D::Error> {
+        let records = Vec::<Record>::deserialize(deserializer)?;
+        Ok(records
+            .into_iter()
+            .map(|record| {
+                let cell = CollisionCell {
+                    kind: record.kind,
+                    shape: record.shape,
+                };
+                (record.point, cell)
+            })
+            .collect())
+    }
+}
+
+/// Int grid cells are written as `{x, y, value}` records, see `tile_layer`.
+mod int_grid_layer {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        #[serde(flatten)]
+        point: HashableVec2,
+        value: u32,
+    }
+
+    pub fn serialize<S: Serializer>(
+        cells: &HashMap<HashableVec2, u32>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            sorted_int_grid_cells(cells)
+                .into_iter()
+                .map(|(point, value)| Record {
+                    point: *point,
+                    value: *value,
+                }),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<HashableVec2, u32>, D::Error> {
+        let records = Vec::<Record>::deserialize(deserializer)?;
+        Ok(records
+            .into_iter()
+            .map(|record| (record.point, record.value))
+            .collect())
+    }
+}
+
+/// Entities are written as `{x, y, label}` records, see `tile_layer`.
+mod entity_layer {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        #[serde(flatten)]
+        point: HashableVec2,
+        label: String,
+    }
+
+    pub fn serialize<S: Serializer>(
+        entities: &HashMap<HashableVec2, String>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            sorted_entities(entities)
+                .into_iter()
+                .map(|(point, label)| Record {
+                    point: *point,
+                    label: label.clone(),
+                }),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<HashableVec2, String>, D::Error> {
+        let records = Vec::<Record>::deserialize(deserializer)?;
+        Ok(records
+            .into_iter()
+            .map(|record| (record.point, record.label))
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSpritesheet {
+    /// Relative to the JSON file, with `/` separators.
+    path: String,
+    width: u32,
+    height: u32,
+    /// FNV-1a of the image file as 16 hex digits, since many JSON parsers can't hold a u64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonLayer {
+    name: String,
+    #[serde(default, with = "tile_layer")]
+    tiles: HashMap<HashableVec2, TileIndex>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonIntGrid {
+    name: String,
+    #[serde(default)]
+    values: Vec<IntGridValue>,
+    #[serde(default, with = "int_grid_layer")]
+    cells: HashMap<HashableVec2, u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonLevel {
+    format_version: u32,
+    spritesheet_info: SpritesheetInfo,
+    #[serde(default)]
+    spritesheet: Option<JsonSpritesheet>,
+    /// Bottom first.
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    /// Version 1's two layers, only read.
+    #[serde(default, skip_serializing, with = "tile_layer")]
+    background: HashMap<HashableVec2, TileIndex>,
+    #[serde(default, skip_serializing, with = "tile_layer")]
+    foreground: HashMap<HashableVec2, TileIndex>,
+    #[serde(default, with = "collision_layer")]
+    collision: HashMap<HashableVec2, CollisionCell>,
+    #[serde(default = "default_collision_kinds")]
+    collision_kinds: Vec<CollisionKind>,
+    #[serde(default)]
+    int_grids: Vec<JsonIntGrid>,
+    #[serde(default, with = "entity_layer")]
+    entities: HashMap<HashableVec2, String>,
+}
+
+impl Level {
+    pub fn export_json(&self, path: &Path) -> Result<(), LevelIoError> {
+        let level_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let level = JsonLevel {
+            format_version: JSON_FORMAT_VERSION,
+            spritesheet_info: self.spritesheet_info,
+            spritesheet: self.spritesheet.as_ref().map(|source| JsonSpritesheet {
+                path: relative_path(level_dir, &source.path),
+                width: source.width,
+                height: source.height,
+                hash: source.hash.map(|hash| format!("{:016x}", hash)),
+            }),
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| JsonLayer {
+                    name: layer.name.clone(),
+                    tiles: layer.tiles.clone(),
+                })
+                .collect(),
+            background: HashMap::new(),
+            foreground: HashMap::new(),
+            collision: self.collision.clone(),
+            collision_kinds: self.collision_kinds.clone(),
+            int_grids: self
+                .int_grids
+                .iter()
+                .map(|layer| JsonIntGrid {
+                    name: layer.name.clone(),
+                    values: layer.values.clone(),
+                    cells: layer.cells.clone(),
+                })
+                .collect(),
+            entities: self.entities.clone(),
+        };
+        let buffer = serde_json::to_vec_pretty(&level)?;
+        std::fs::write(path, buffer)?;
+        Ok(())
+    }
+
+    /// Reads a level written by `export_json`. Its spritesheet settings always replace `base`'s,
+    /// but `base`'s spritesheet is kept if the file doesn't name one.
+    pub fn import_json(path: &Path, base: Level) -> Result<Level, LevelIoError> {
+        let buf = std::fs::read(path)?;
+        let level: JsonLevel = serde_json::from_slice(&buf)?;
+        if level.format_version > JSON_FORMAT_VERSION {
+            return Err(LevelIoError::UnsupportedVersion(
+                level.format_version.try_into().unwrap_or(u16::MAX),
+            ));
+        }
+        check_spritesheet_info(&level.spritesheet_info)?;
+        let level_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let spritesheet = match level.spritesheet {
+            Some(sheet) => Some(SpritesheetSource {
+                path: level_dir.join(sheet.path),
+                width: sheet.width,
+                height: sheet.height,
+                hash: sheet
+                    .hash
+                    .and_then(|hash| u64::from_str_radix(&hash, 16).ok()),
+            }),
+            None => base.spritesheet,
+        };
+        let layers = if level.format_version < 2 {
+            DEFAULT_LAYER_NAMES
+                .into_iter()
+                .zip([level.background, level.foreground])
+                .map(|(name, tiles)| TileLayer {
+                    name: name.to_string(),
+                    tiles,
+                })
+                .collect()
+        } else {
+            level
+                .layers
+                .into_iter()
+                .map(|layer| TileLayer {
+                    name: layer.name,
+                    tiles: layer.tiles,
+                })
+                .collect()
+        };
+        let level = Level {
+            spritesheet_info: level.spritesheet_info,
+            spritesheet,
+            layers,
+            collision: level.collision,
+            collision_kinds: level.collision_kinds,
+            int_grids: level
+                .int_grids
+                .into_iter()
+                .map(|layer| IntGridLayer {
+                    name: layer.name,
+                    values: layer.values,
+                    cells: layer.cells,
+                })
+                .collect(),
+            entities: level.entities,
+            ..base
+        };
+        level.check_collision()?;
+        level.check_int_grids()?;
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{sample_level, test_dir};
+    use crate::Level;
+
+    #[test]
+    fn round_trip() {
+        let dir = test_dir("json-round-trip");
+        let level = sample_level(&dir);
+        let path = dir.join("level.json");
+        level.export_json(&path).unwrap();
+        let read = Level::import_json(&path, Level::default()).unwrap();
+        assert_eq!(read, level);
+    }
+}
@@ -0,0 +1,735 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::file::{check_grid_size, hash_bytes, relative_path, LevelIoError};
+use crate::{CollisionShape, HashableVec2, IntGridLayer, Level, LevelBounds, TileIndex, TileLayer};
+
+/// LDtk version the exported projects claim to be written by.
+const LDTK_VERSION: &str = "1.1.3";
+const LEVEL_BG_COLOR: &str = "#696A79";
+
+/// Hands out the unique ids LDtk uses to link definitions and instances together.
+struct UidAllocator(i64);
+
+impl UidAllocator {
+    fn next(&mut self) -> i64 {
+        self.0 += 1;
+        self.0
+    }
+}
+
+/// LDtk wants a UUID on every project, level, layer and entity instance. They are derived from
+/// `seed` so exporting the same level twice gives the same file.
+fn iid(seed: &str) -> String {
+    let high = hash_bytes(seed.as_bytes());
+    let low = hash_bytes(format!("{}#", seed).as_bytes());
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Turns an entity label into an LDtk identifier: letters, digits and underscores, starting
+/// with a capital letter. Trailing digits are dropped so `Enemy_1` and `Enemy_2` share a
+/// definition; the full label is kept in each instance's `label` field.
+fn entity_identifier(label: &str) -> String {
+    let sanitized: String = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let trimmed = sanitized.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+    let trimmed = trimmed.trim_start_matches(|c: char| c.is_ascii_digit() || c == '_');
+    let mut chars = trimmed.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => "Entity".to_string(),
+    }
+}
+
+/// Turns a collision kind's or int grid value's name into an IntGrid value identifier,
+/// `One-way Platform` becoming `one_way_platform`, or null when nothing of it is left.
+fn int_grid_value_identifier(name: &str) -> Value {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect();
+    match sanitized.trim_matches(|c: char| c.is_ascii_digit() || c == '_') {
+        "" => Value::Null,
+        identifier => json!(identifier),
+    }
+}
+
+/// The `intGridValues` of an IntGrid layer definition, from each value's number, name and color.
+fn int_grid_values<'a>(values: impl Iterator<Item = (u32, &'a str, [u8; 3])>) -> Value {
+    let values: Vec<Value> = values
+        .map(|(value, name, [r, g, b])| {
+            json!({
+                "value": value,
+                "identifier": int_grid_value_identifier(name),
+                "color": format!("#{:02X}{:02X}{:02X}", r, g, b),
+            })
+        })
+        .collect();
+    json!(values)
+}
+
+/// An IntGrid layer instance's `intGridCsv`, every cell over `bounds` row by row, 0 for empty.
+fn int_grid_csv<'a>(
+    cells: impl Iterator<Item = (&'a HashableVec2, u32)>,
+    bounds: LevelBounds,
+) -> Vec<u32> {
+    let mut csv = vec![0; (bounds.width() * bounds.height()) as usize];
+    for (point, value) in cells {
+        let (col, row) = bounds.to_cell(point);
+        csv[(row * bounds.width() + col) as usize] = value;
+    }
+    csv
+}
+
+/// IntGrid cells can't have shapes, so cells that aren't full are marked in a second IntGrid layer
+/// with the shape's place in `CollisionShape::PRESETS`, which leaves full cells at 0, or one past
+/// the presets for polygons.
+fn collision_shape_value(shape: &CollisionShape) -> u32 {
+    CollisionShape::PRESETS
+        .iter()
+        .position(|preset| preset == shape)
+        .unwrap_or(CollisionShape::PRESETS.len()) as u32
+}
+
+/// The `intGridValues` of the collision shapes layer, see `collision_shape_value`.
+fn collision_shape_values() -> Value {
+    let names: Vec<String> = CollisionShape::PRESETS[1..]
+        .iter()
+        .map(CollisionShape::name)
+        .chain(["Polygon".to_string()])
+        .collect();
+    int_grid_values((1..).zip(&names).map(|(value, name)| {
+        let [_, r, g, b] = (hash_bytes(name.as_bytes()) as u32).to_be_bytes();
+        (value, name.as_str(), [r, g, b])
+    }))
+}
+
+/// Turns layer names into unique LDtk identifiers, `Far hills` becoming `Far_hills`. Names that
+/// are taken, including by the collision and entity layers, get a number on the end.
+fn layer_identifiers<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut used: HashSet<String> = ["Entities", "Collision", "Collision_Shapes"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    names
+        .map(|name| {
+            let sanitized: String = name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            let trimmed = sanitized.trim_matches('_');
+            let trimmed = trimmed.trim_start_matches(|c: char| c.is_ascii_digit() || c == '_');
+            let mut chars = trimmed.chars();
+            let identifier = match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => "Tiles".to_string(),
+            };
+            let mut unique = identifier.clone();
+            let mut n = 2;
+            while used.contains(&unique) {
+                unique = format!("{}_{}", identifier, n);
+                n += 1;
+            }
+            used.insert(unique.clone());
+            unique
+        })
+        .collect()
+}
+
+fn layer_def(
+    identifier: &str,
+    kind: &str,
+    uid: i64,
+    grid_size: u32,
+    tileset: Option<i64>,
+    int_grid_values: Value,
+) -> Value {
+    json!({
+        "__type": kind,
+        "identifier": identifier,
+        "type": kind,
+        "uid": uid,
+        "gridSize": grid_size,
+        "guideGridWid": 0,
+        "guideGridHei": 0,
+        "displayOpacity": 1,
+        "inactiveOpacity": 1,
+        "hideInList": false,
+        "hideFieldsWhenInactive": false,
+        "pxOffsetX": 0,
+        "pxOffsetY": 0,
+        "parallaxFactorX": 0,
+        "parallaxFactorY": 0,
+        "parallaxScaling": true,
+        "requiredTags": [],
+        "excludedTags": [],
+        "intGridValues": int_grid_values,
+        "autoRuleGroups": [],
+        "autoSourceLayerDefUid": null,
+        "tilesetDefUid": tileset,
+        "tilePivotX": 0,
+        "tilePivotY": 0
+    })
+}
+
+/// Entity definitions carry the original label in a string field, since identifiers can't
+/// hold arbitrary text.
+fn label_field_def(uid: i64) -> Value {
+    json!({
+        "identifier": "label",
+        "__type": "String",
+        "uid": uid,
+        "type": "F_String",
+        "isArray": false,
+        "canBeNull": false,
+        "arrayMinLength": null,
+        "arrayMaxLength": null,
+        "editorDisplayMode": "ValueOnly",
+        "editorDisplayPos": "Above",
+        "editorAlwaysShow": false,
+        "editorCutLongValues": true,
+        "min": null,
+        "max": null,
+        "regex": null,
+        "acceptFileTypes": null,
+        "defaultOverride": null,
+        "textLanguageMode": null,
+        "symmetricalRef": false,
+        "autoChainRef": true,
+        "allowOutOfLevelRef": true,
+        "allowedRefs": "OnlySame",
+        "allowedRefTags": [],
+        "tilesetUid": null,
+        "useForSmartColor": false
+    })
+}
+
+/// Everything a layer instance repeats from its definition and the level.
+struct LayerContext<'a> {
+    bounds: LevelBounds,
+    grid_size: u32,
+    level_uid: i64,
+    tileset: Option<(i64, &'a Option<String>)>,
+}
+
+fn layer_instance(
+    context: &LayerContext,
+    identifier: &str,
+    kind: &str,
+    def_uid: i64,
+    contents: Value,
+) -> Value {
+    let mut layer = json!({
+        "__identifier": identifier,
+        "__type": kind,
+        "__cWid": context.bounds.width(),
+        "__cHei": context.bounds.height(),
+        "__gridSize": context.grid_size,
+        "__opacity": 1,
+        "__pxTotalOffsetX": 0,
+        "__pxTotalOffsetY": 0,
+        "__tilesetDefUid": context.tileset.map(|(uid, _)| uid),
+        "__tilesetRelPath": context.tileset.and_then(|(_, path)| path.clone()),
+        "iid": iid(&format!("layer:{}", identifier)),
+        "levelId": context.level_uid,
+        "layerDefUid": def_uid,
+        "pxOffsetX": 0,
+        "pxOffsetY": 0,
+        "visible": true,
+        "optionalRules": [],
+        "intGridCsv": [],
+        "autoLayerTiles": [],
+        "seed": 0,
+        "overrideTilesetUid": null,
+        "gridTiles": [],
+        "entityInstances": []
+    });
+    if let (Some(layer), Value::Object(contents)) = (layer.as_object_mut(), contents) {
+        layer.extend(contents);
+    }
+    layer
+}
+
+impl Level {
+    fn ldtk_grid_tiles(
+        &self,
+        tiles: &HashMap<HashableVec2, TileIndex>,
+        bounds: LevelBounds,
+        tile_size: (u32, u32),
+        grid_size: u32,
+    ) -> Value {
+        let columns = self.spritesheet_info.num_rows as i64;
+        let mut cells: Vec<((i64, i64), TileIndex)> = tiles
+            .iter()
+            .map(|(point, tile)| (bounds.to_cell(point), *tile))
+            .collect();
+        cells.sort_by_key(|((col, row), _)| (*row, *col));
+        cells
+            .into_iter()
+            .map(|((col, row), tile)| {
+                json!({
+                    "px": [col * grid_size as i64, row * grid_size as i64],
+                    "src": [tile.col as u32 * tile_size.0, tile.row as u32 * tile_size.1],
+                    "f": 0,
+                    "t": tile.row as i64 * columns + tile.col as i64,
+                    "d": [row * bounds.width() + col]
+                })
+            })
+            .collect()
+    }
+
+    /// Builds a single level LDtk project. LDtk grids are square, so the tile width is used as
+    /// the grid size.
+    fn ldtk_project(&self, project_path: &Path) -> Value {
+        let bounds = self.bounds();
+        let tile_size = self.tile_pixel_size();
+        let grid_size = tile_size.0;
+        let columns = self.spritesheet_info.num_rows as u32;
+        let rows = self.spritesheet_info.num_cols as u32;
+        let project_dir = project_path.parent().unwrap_or_else(|| Path::new(""));
+        let tileset_path = self
+            .spritesheet
+            .as_ref()
+            .map(|source| relative_path(project_dir, &source.path));
+        let mut uids = UidAllocator(0);
+
+        let tileset_uid = uids.next();
+        let tileset_def = json!({
+            "__cWid": columns,
+            "__cHei": rows,
+            "identifier": "Spritesheet",
+            "uid": tileset_uid,
+            "relPath": tileset_path,
+            "embedAtlas": null,
+            "pxWid": columns * tile_size.0,
+            "pxHei": rows * tile_size.1,
+            "tileGridSize": grid_size,
+            "spacing": 0,
+            "padding": 0,
+            "tags": [],
+            "tagsSourceEnumUid": null,
+            "enumTags": [],
+            "customData": [],
+            "savedSelections": [],
+            "cachedPixelData": null
+        });
+
+        let entities_uid = uids.next();
+        let collision_uid = uids.next();
+        let has_shapes = self.collision.values().any(|cell| !cell.shape.is_full());
+        let shapes_uid = has_shapes.then(|| uids.next());
+        // LDtk lists layers top first, and int grids annotate the tiles so they go above them
+        let mut identifiers = layer_identifiers(
+            self.int_grids
+                .iter()
+                .map(|layer| layer.name.as_str())
+                .chain(self.layers.iter().rev().map(|layer| layer.name.as_str())),
+        );
+        let tile_identifiers = identifiers.split_off(self.int_grids.len());
+        let int_grid_layers: Vec<(String, i64, &IntGridLayer)> = identifiers
+            .into_iter()
+            .zip(&self.int_grids)
+            .map(|(identifier, layer)| (identifier, uids.next(), layer))
+            .collect();
+        let tile_layers: Vec<(String, i64, &TileLayer)> = tile_identifiers
+            .into_iter()
+            .zip(self.layers.iter().rev())
+            .map(|(identifier, layer)| (identifier, uids.next(), layer))
+            .collect();
+        // IntGrid value 0 is an empty cell, so kind 0 is 1
+        let collision_values = int_grid_values(
+            (1..)
+                .zip(&self.collision_kinds)
+                .map(|(value, kind)| (value, kind.name.as_str(), kind.color)),
+        );
+        let mut layer_defs = vec![
+            layer_def(
+                "Entities",
+                "Entities",
+                entities_uid,
+                grid_size,
+                None,
+                json!([]),
+            ),
+            layer_def(
+                "Collision",
+                "IntGrid",
+                collision_uid,
+                grid_size,
+                None,
+                collision_values,
+            ),
+        ];
+        if let Some(uid) = shapes_uid {
+            layer_defs.push(layer_def(
+                "Collision_Shapes",
+                "IntGrid",
+                uid,
+                grid_size,
+                None,
+                collision_shape_values(),
+            ));
+        }
+        layer_defs.extend(int_grid_layers.iter().map(|(identifier, uid, layer)| {
+            let values = layer
+                .values
+                .iter()
+                .map(|value| (value.value, value.name.as_str(), value.color));
+            layer_def(
+                identifier,
+                "IntGrid",
+                *uid,
+                grid_size,
+                None,
+                int_grid_values(values),
+            )
+        }));
+        layer_defs.extend(tile_layers.iter().map(|(identifier, uid, _)| {
+            layer_def(
+                identifier,
+                "Tiles",
+                *uid,
+                grid_size,
+                Some(tileset_uid),
+                json!([]),
+            )
+        }));
+
+        // One definition per identifier, each with a `label` field holding the original label.
+        // Uids go out in identifier order, not the entity map's, so exports are repeatable.
+        let entity_identifiers: BTreeSet<String> = self
+            .entities
+            .values()
+            .map(|label| entity_identifier(label))
+            .collect();
+        let entity_defs: BTreeMap<String, (i64, i64)> = entity_identifiers
+            .into_iter()
+            .map(|identifier| (identifier, (uids.next(), uids.next())))
+            .collect();
+        let entity_def_values: Vec<Value> = entity_defs
+            .iter()
+            .map(|(identifier, (uid, field_uid))| {
+                let color = format!("#{:06X}", hash_bytes(identifier.as_bytes()) & 0xff_ffff);
+                json!({
+                    "identifier": identifier,
+                    "uid": uid,
+                    "tags": [],
+                    "width": grid_size,
+                    "height": grid_size,
+                    "resizableX": false,
+                    "resizableY": false,
+                    "keepAspectRatio": false,
+                    "tileOpacity": 1,
+                    "fillOpacity": 1,
+                    "lineOpacity": 1,
+                    "hollow": false,
+                    "color": color,
+                    "renderMode": "Rectangle",
+                    "showName": true,
+                    "tilesetId": null,
+                    "tileRenderMode": "FitInside",
+                    "tileRect": null,
+                    "nineSliceBorders": [],
+                    "maxCount": 0,
+                    "limitScope": "PerLevel",
+                    "limitBehavior": "MoveLastOne",
+                    "pivotX": 0,
+                    "pivotY": 0,
+                    "fieldDefs": [label_field_def(*field_uid)]
+                })
+            })
+            .collect();
+
+        let level_uid = uids.next();
+        let context = LayerContext {
+            bounds,
+            grid_size,
+            level_uid,
+            tileset: None,
+        };
+
+        let mut entities: Vec<((i64, i64), &String)> = self
+            .entities
+            .iter()
+            .map(|(point, label)| (bounds.to_cell(point), label))
+            .collect();
+        entities.sort();
+        let entity_instances: Vec<Value> = entities
+            .into_iter()
+            .map(|((col, row), label)| {
+                let identifier = entity_identifier(label);
+                let (def_uid, field_uid) = entity_defs[&identifier];
+                let color = format!("#{:06X}", hash_bytes(identifier.as_bytes()) & 0xff_ffff);
+                let px = [col * grid_size as i64, row * grid_size as i64];
+                json!({
+                    "__identifier": identifier,
+                    "__grid": [col, row],
+                    "__pivot": [0, 0],
+                    "__tags": [],
+                    "__tile": null,
+                    "__smartColor": color,
+                    "iid": iid(&format!("entity:{}:{}:{}", col, row, label)),
+                    "width": grid_size,
+                    "height": grid_size,
+                    "defUid": def_uid,
+                    "px": px,
+                    "__worldX": px[0],
+                    "__worldY": px[1],
+                    "fieldInstances": [{
+                        "__identifier": "label",
+                        "__value": label,
+                        "__type": "String",
+                        "__tile": null,
+                        "defUid": field_uid,
+                        "realEditorValues": [{ "id": "V_String", "params": [label] }]
+                    }]
+                })
+            })
+            .collect();
+
+        let collision_csv = int_grid_csv(
+            self.collision
+                .iter()
+                .map(|(point, cell)| (point, cell.kind as u32 + 1)),
+            bounds,
+        );
+
+        let tiles_context = LayerContext {
+            tileset: Some((tileset_uid, &tileset_path)),
+            ..context
+        };
+        let mut layer_instances = vec![
+            layer_instance(
+                &context,
+                "Entities",
+                "Entities",
+                entities_uid,
+                json!({ "entityInstances": entity_instances }),
+            ),
+            layer_instance(
+                &context,
+                "Collision",
+                "IntGrid",
+                collision_uid,
+                json!({ "intGridCsv": collision_csv }),
+            ),
+        ];
+        if let Some(uid) = shapes_uid {
+            let shapes_csv = int_grid_csv(
+                self.collision
+                    .iter()
+                    .map(|(point, cell)| (point, collision_shape_value(&cell.shape))),
+                bounds,
+            );
+            layer_instances.push(layer_instance(
+                &context,
+                "Collision_Shapes",
+                "IntGrid",
+                uid,
+                json!({ "intGridCsv": shapes_csv }),
+            ));
+        }
+        layer_instances.extend(int_grid_layers.iter().map(|(identifier, uid, layer)| {
+            layer_instance(
+                &context,
+                identifier,
+                "IntGrid",
+                *uid,
+                json!({
+                    "intGridCsv": int_grid_csv(
+                        layer.cells.iter().map(|(point, value)| (point, *value)),
+                        bounds
+                    )
+                }),
+            )
+        }));
+        layer_instances.extend(tile_layers.iter().map(|(identifier, uid, layer)| {
+            layer_instance(
+                &tiles_context,
+                identifier,
+                "Tiles",
+                *uid,
+                json!({
+                    "gridTiles": self.ldtk_grid_tiles(&layer.tiles, bounds, tile_size, grid_size)
+                }),
+            )
+        }));
+
+        let px_wid = bounds.width() * grid_size as i64;
+        let px_hei = bounds.height() * grid_size as i64;
+        let level = json!({
+            "identifier": "Level_0",
+            "iid": iid("level:0"),
+            "uid": level_uid,
+            "worldX": 0,
+            "worldY": 0,
+            "worldDepth": 0,
+            "pxWid": px_wid,
+            "pxHei": px_hei,
+            "__bgColor": LEVEL_BG_COLOR,
+            "bgColor": null,
+            "useAutoIdentifier": true,
+            "bgRelPath": null,
+            "bgPos": null,
+            "bgPivotX": 0.5,
+            "bgPivotY": 0.5,
+            "__smartColor": "#ADADB5",
+            "__bgPos": null,
+            "externalRelPath": null,
+            "fieldInstances": [],
+            "layerInstances": layer_instances,
+            "__neighbours": []
+        });
+
+        json!({
+            "__header__": {
+                "fileType": "LDtk Project JSON",
+                "app": "LDtk",
+                "doc": "https://ldtk.io/json",
+                "schema": "https://ldtk.io/files/JSON_SCHEMA.json",
+                "appAuthor": "Sebastien 'deepnight' Benard",
+                "appVersion": LDTK_VERSION,
+                "url": "https://ldtk.io"
+            },
+            "iid": iid("project"),
+            "jsonVersion": LDTK_VERSION,
+            "appBuildId": 0,
+            "nextUid": uids.next(),
+            "identifierStyle": "Capitalize",
+            "worldLayout": "Free",
+            "worldGridWidth": px_wid,
+            "worldGridHeight": px_hei,
+            "defaultLevelWidth": px_wid,
+            "defaultLevelHeight": px_hei,
+            "defaultPivotX": 0,
+            "defaultPivotY": 0,
+            "defaultGridSize": grid_size,
+            "bgColor": "#40465B",
+            "defaultLevelBgColor": LEVEL_BG_COLOR,
+            "minifyJson": false,
+            "externalLevels": false,
+            "exportTiled": false,
+            "simplifiedExport": false,
+            "imageExportMode": "None",
+            "pngFilePattern": null,
+            "backupOnSave": false,
+            "backupLimit": 10,
+            "levelNamePattern": "Level_%idx",
+            "tutorialDesc": null,
+            "flags": [],
+            "defs": {
+                "layers": layer_defs,
+                "entities": entity_def_values,
+                "tilesets": [tileset_def],
+                "enums": [],
+                "externalEnums": [],
+                "levelFields": []
+            },
+            "levels": [level],
+            "worlds": []
+        })
+    }
+
+    /// Returns a line for everything in the level the project couldn't hold.
+    pub fn export_ldtk(&self, path: &Path) -> Result<Vec<String>, LevelIoError> {
+        // IntGrid layers store every cell of the level
+        check_grid_size(self.bounds())?;
+        let project = self.ldtk_project(path);
+        let buffer = serde_json::to_vec_pretty(&project)?;
+        std::fs::write(path, buffer)?;
+        let polygons = self
+            .collision
+            .values()
+            .filter(|cell| matches!(cell.shape, CollisionShape::Polygon(_)))
+            .count();
+        let mut report = Vec::new();
+        if polygons > 0 {
+            report.push(format!(
+                "{} collision polygons were marked as \"Polygon\" in Collision_Shapes without \
+                 their corners, LDtk IntGrid cells can't hold them",
+                polygons
+            ));
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{reinserted, sample_level, test_dir};
+
+    #[test]
+    fn exported_project_has_every_layer() {
+        let dir = test_dir("ldtk-layers");
+        let level = sample_level(&dir);
+        let path = dir.join("level.ldtk");
+        let report = level.export_ldtk(&path).unwrap();
+        assert_eq!(report.len(), 1, "only the polygon is flattened");
+        let project: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let identifiers = |layers: &Value, key: &str| {
+            let layers = layers.as_array().unwrap().iter();
+            layers
+                .map(|layer| layer[key].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let instances = identifiers(&project["levels"][0]["layerInstances"], "__identifier");
+        // top first, with the int grid above the tiles
+        assert_eq!(
+            instances,
+            [
+                "Entities",
+                "Collision",
+                "Collision_Shapes",
+                "Rooms",
+                "Far_Hills",
+                "Foreground",
+                "Background"
+            ]
+        );
+        assert_eq!(
+            identifiers(&project["defs"]["layers"], "identifier"),
+            instances
+        );
+    }
+
+    #[test]
+    fn far_apart_cells_are_refused() {
+        let dir = test_dir("ldtk-far-apart");
+        let mut level = Level::default();
+        let point = |x, y| HashableVec2 { x, y };
+        level.entities = HashMap::from([
+            (point(0, 0), "a".to_string()),
+            (point(1 << 40, 1 << 40), "b".to_string()),
+        ]);
+        assert!(matches!(
+            level.export_ldtk(&dir.join("level.ldtk")),
+            Err(LevelIoError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn exports_are_deterministic() {
+        let dir = test_dir("ldtk-deterministic");
+        let level = sample_level(&dir);
+        let path = dir.join("level.ldtk");
+        level.export_ldtk(&path).unwrap();
+        let first = std::fs::read(&path).unwrap();
+        reinserted(&level).export_ldtk(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), first);
+    }
+}
@@ -0,0 +1,278 @@
+//! The level model shared by the editor, `lvl-tool` and games that load levels at runtime, along
+//! with every format it can be read from and written to. Nothing here depends on egui.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "cli")]
+pub mod cli;
+mod csv;
+mod file;
+pub use file::{hash_bytes, relative_path, replace_file, LevelIoError, SAVE_BACKUPS};
+
+mod json;
+mod ldtk;
+mod text;
+pub use text::{is_text_level, TEXT_LEVEL_EXTENSION};
+mod tiled;
+
+mod types;
+pub use types::*;
+
+/// One named layer of tiles. Layer names are what the user sees and what other formats call the
+/// layer, they don't have to be unique.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub tiles: HashMap<HashableVec2, TileIndex>,
+}
+
+impl TileLayer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tiles: HashMap::new(),
+        }
+    }
+}
+
+/// A named layer of integers for annotating a level, e.g. with room ids or audio regions. Every
+/// cell holds one of the layer's `values`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntGridLayer {
+    pub name: String,
+    pub values: Vec<IntGridValue>,
+    pub cells: HashMap<HashableVec2, u32>,
+}
+
+impl IntGridLayer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            values: Vec::new(),
+            cells: HashMap::new(),
+        }
+    }
+    /// The definition of `value`, if the layer has one.
+    pub fn value(&self, value: u32) -> Option<&IntGridValue> {
+        self.values.iter().find(|defined| defined.value == value)
+    }
+}
+
+/// A level: any number of tile layers, collision cells of different kinds and shapes, int grid
+/// layers and labelled entities on an unbounded grid, plus the spritesheet the tiles index into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub spritesheet_info: SpritesheetInfo,
+    pub spritesheet: Option<SpritesheetSource>,
+    /// Drawn in order, so the first layer is at the bottom.
+    pub layers: Vec<TileLayer>,
+    /// The kind and shape of every cell with collision.
+    pub collision: HashMap<HashableVec2, CollisionCell>,
+    pub collision_kinds: Vec<CollisionKind>,
+    pub int_grids: Vec<IntGridLayer>,
+    pub entities: HashMap<HashableVec2, String>,
+    /// Whether `save` compresses the binary format. Opening a compressed level sets it, so the
+    /// level stays compressed when saved again.
+    pub compressed: bool,
+}
+
+/// Names of the layers a new level starts with, bottom first. Formats from before layers had
+/// names stored exactly these two.
+pub const DEFAULT_LAYER_NAMES: [&str; 2] = ["Background", "Foreground"];
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            spritesheet_info: SpritesheetInfo::default(),
+            spritesheet: None,
+            layers: DEFAULT_LAYER_NAMES
+                .into_iter()
+                .map(TileLayer::new)
+                .collect(),
+            collision: HashMap::new(),
+            collision_kinds: default_collision_kinds(),
+            int_grids: Vec::new(),
+            entities: HashMap::new(),
+            compressed: false,
+        }
+    }
+}
+
+impl Level {
+    /// An empty level with the same spritesheet. Readers start from one of these, so files that
+    /// don't record a spritesheet keep using the caller's.
+    pub fn with_same_spritesheet(&self) -> Level {
+        Level {
+            spritesheet_info: self.spritesheet_info,
+            spritesheet: self.spritesheet.clone(),
+            ..Level::default()
+        }
+    }
+    /// Whether nothing has been placed in the level.
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(|layer| layer.tiles.is_empty())
+            && self.collision.is_empty()
+            && self.int_grids.iter().all(|layer| layer.cells.is_empty())
+            && self.entities.is_empty()
+    }
+    /// Bounds of every placed tile, collision cell, int grid cell and entity. An empty level is
+    /// treated as a single cell at the origin.
+    pub fn bounds(&self) -> LevelBounds {
+        let points = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.tiles.keys())
+            .chain(self.collision.keys())
+            .chain(self.int_grids.iter().flat_map(|layer| layer.cells.keys()))
+            .chain(self.entities.keys());
+        let mut bounds: Option<LevelBounds> = None;
+        for point in points {
+            let bounds = bounds.get_or_insert(LevelBounds {
+                min_x: point.x,
+                max_x: point.x,
+                min_y: point.y,
+                max_y: point.y,
+            });
+            bounds.min_x = bounds.min_x.min(point.x);
+            bounds.max_x = bounds.max_x.max(point.x);
+            bounds.min_y = bounds.min_y.min(point.y);
+            bounds.max_y = bounds.max_y.max(point.y);
+        }
+        bounds.unwrap_or(LevelBounds {
+            min_x: 0,
+            max_x: 0,
+            min_y: 0,
+            max_y: 0,
+        })
+    }
+    /// Width and height of one tile on the spritesheet in pixels, falling back to the sprite
+    /// size setting when the level has no spritesheet.
+    pub fn tile_pixel_size(&self) -> (u32, u32) {
+        match &self.spritesheet {
+            Some(source) => (
+                source.width / self.spritesheet_info.num_rows as u32,
+                source.height / self.spritesheet_info.num_cols as u32,
+            ),
+            None => (
+                self.spritesheet_info.sprite_size as u32,
+                self.spritesheet_info.sprite_size as u32,
+            ),
+        }
+    }
+}
+
+/// A level with something in every part of the model, and scratch directories for the formats
+/// that read and write files.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    /// An empty directory of its own for each test, under the system's temporary directory.
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rs-level-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The same level with every map rebuilt, so its entries most likely iterate in a different
+    /// order.
+    pub(crate) fn reinserted(level: &Level) -> Level {
+        fn rebuild<K: Clone + Eq + std::hash::Hash, V: Clone>(
+            map: &HashMap<K, V>,
+        ) -> HashMap<K, V> {
+            let mut entries: Vec<_> = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            entries.reverse();
+            entries.into_iter().collect()
+        }
+        let mut level = level.clone();
+        for layer in &mut level.layers {
+            layer.tiles = rebuild(&layer.tiles);
+        }
+        level.collision = rebuild(&level.collision);
+        for layer in &mut level.int_grids {
+            layer.cells = rebuild(&layer.cells);
+        }
+        level.entities = rebuild(&level.entities);
+        level
+    }
+
+    /// Tiles in three layers, one of them empty, collision of a custom kind and every sort of
+    /// shape, an int grid and entities, on both sides of the origin. The spritesheet sits in
+    /// `dir`, where levels written by the tests go too.
+    pub(crate) fn sample_level(dir: &Path) -> Level {
+        let point = |x, y| HashableVec2 { x, y };
+        let tile = |col, row| TileIndex { col, row };
+        let mut level = Level {
+            spritesheet_info: SpritesheetInfo {
+                sprite_size: 16,
+                num_rows: 4,
+                num_cols: 4,
+            },
+            spritesheet: Some(SpritesheetSource {
+                path: dir.join("sheet.png"),
+                width: 64,
+                height: 64,
+                hash: Some(0x0123_4567_89ab_cdef),
+            }),
+            ..Level::default()
+        };
+        level.layers[0].tiles = HashMap::from([
+            (point(0, 0), tile(1, 2)),
+            (point(-3, 5), tile(0, 0)),
+            (point(2, -1), tile(3, 3)),
+        ]);
+        level.layers[1].tiles = HashMap::from([(point(0, 0), tile(2, 1))]);
+        level.layers.push(TileLayer::new("Far Hills"));
+        level
+            .collision_kinds
+            .push(CollisionKind::new("Spikes", [1, 2, 3]));
+        let spikes = level.collision_kinds.len() as u16 - 1;
+        level.collision = HashMap::from([
+            (point(0, 0), CollisionCell::full(0)),
+            (
+                point(1, 0),
+                CollisionCell {
+                    kind: 1,
+                    shape: CollisionShape::HalfTop,
+                },
+            ),
+            (
+                point(2, 0),
+                CollisionCell {
+                    kind: 0,
+                    shape: CollisionShape::Slope45(Corner::TopRight),
+                },
+            ),
+            (
+                point(-1, 3),
+                CollisionCell {
+                    kind: spikes,
+                    shape: CollisionShape::Polygon(vec![[0.0, 0.0], [1.0, 0.0], [0.5, 1.0]]),
+                },
+            ),
+        ]);
+        let mut rooms = IntGridLayer::new("Rooms");
+        rooms.values = vec![
+            IntGridValue {
+                value: 1,
+                name: "Hall".to_string(),
+                color: [10, 20, 30],
+            },
+            IntGridValue {
+                value: 7,
+                name: "Vault".to_string(),
+                color: [200, 100, 0],
+            },
+        ];
+        rooms.cells = HashMap::from([(point(0, 1), 1), (point(5, 5), 7)]);
+        level.int_grids.push(rooms);
+        level.entities = HashMap::from([
+            (point(0, 2), "Player".to_string()),
+            (point(4, -2), "Enemy 1".to_string()),
+        ]);
+        level
+    }
+}
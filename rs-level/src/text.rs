@@ -0,0 +1,554 @@
+//! A RON level format meant to be read in pull requests and edited by hand.
+//!
+//! Tile layers, collision and int grids are stored as rows of text over the level's bounds, top
+//! row first, starting at `origin`. A tile row is a space separated list of spritesheet indices
+//! (`row * tiles across + col`), where `.` is an empty cell and `index*count` repeats a cell, e.g.
+//! `". 12*3 . 4"`. Int grid rows are written the same way with the cells' values. A collision row
+//! has one character per cell, `.` for empty and otherwise the `symbol` of the cell's collision
+//! kind, `#` for the first. Empty cells at the end of a row are left off, so rows may be shorter
+//! than the level is wide. Collision cells that aren't full are listed in `collision_shapes` by
+//! their level coordinates, like entities.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::file::{
+    check_grid_size, check_spritesheet_info, relative_path, sorted_collision, sorted_entities,
+    LevelIoError,
+};
+use crate::{
+    default_collision_kinds, CollisionCell, CollisionKind, CollisionShape, HashableVec2,
+    IntGridLayer, IntGridValue, Level, LevelBounds, SpritesheetInfo, SpritesheetSource, TileIndex,
+    TileLayer, DEFAULT_LAYER_NAMES,
+};
+
+/// 2 replaced `background` and `foreground` with a list of named `layers`, 3 added
+/// `collision_kinds`, 4 added `int_grids`, 5 added `collision_shapes`.
+const TEXT_FORMAT_VERSION: u32 = 5;
+/// Levels saved to or opened from a path with this extension use the text format.
+pub const TEXT_LEVEL_EXTENSION: &str = "ron";
+
+const EMPTY_TILE: &str = ".";
+const RUN_SEPARATOR: char = '*';
+const EMPTY: char = '.';
+/// Rows can't fill cells this far in, so a run like `3*4000000000` is refused instead of filling
+/// billions of cells. Empty cells only move along the row and may go further.
+const MAX_FILLED_COLUMN: i64 = 1 << 20;
+/// Written for each collision kind in turn. Before version 3 every collision cell was `#`, the
+/// first kind.
+const COLLISION_SYMBOLS: &str = "#ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+#[derive(Serialize, Deserialize)]
+struct TextSpritesheet {
+    /// Relative to the level file, with `/` separators.
+    path: String,
+    width: u32,
+    height: u32,
+    /// FNV-1a of the image file as 16 hex digits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextEntity {
+    x: i64,
+    y: i64,
+    label: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextCollisionShape {
+    x: i64,
+    y: i64,
+    shape: CollisionShape,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextCollisionKind {
+    name: String,
+    color: [u8; 3],
+    /// Marks cells of this kind in collision rows.
+    symbol: char,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextLayer {
+    name: String,
+    #[serde(default)]
+    rows: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextIntGrid {
+    name: String,
+    #[serde(default)]
+    values: Vec<IntGridValue>,
+    #[serde(default)]
+    rows: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextLevel {
+    format_version: u32,
+    spritesheet_info: SpritesheetInfo,
+    #[serde(default)]
+    spritesheet: Option<TextSpritesheet>,
+    /// Level coordinates of the first cell of the first row.
+    origin: HashableVec2,
+    /// Bottom first.
+    #[serde(default)]
+    layers: Vec<TextLayer>,
+    /// Version 1's two layers, only read.
+    #[serde(default, skip_serializing)]
+    background: Vec<String>,
+    #[serde(default, skip_serializing)]
+    foreground: Vec<String>,
+    #[serde(default)]
+    collision_kinds: Vec<TextCollisionKind>,
+    #[serde(default)]
+    collision: Vec<String>,
+    #[serde(default)]
+    collision_shapes: Vec<TextCollisionShape>,
+    #[serde(default)]
+    int_grids: Vec<TextIntGrid>,
+    #[serde(default)]
+    entities: Vec<TextEntity>,
+}
+
+/// Whether `path` names a text level rather than a binary one.
+pub fn is_text_level(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(TEXT_LEVEL_EXTENSION))
+}
+
+fn invalid(why: impl ToString) -> LevelIoError {
+    LevelIoError::InvalidText(why.to_string())
+}
+
+/// Run-length encodes one row of cells, dropping the empty cells at its end.
+fn encode_tile_row(cells: &[Option<u32>]) -> String {
+    let used = cells
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |last| last + 1);
+    let mut runs: Vec<(Option<u32>, usize)> = Vec::new();
+    for cell in &cells[..used] {
+        match runs.last_mut() {
+            Some((value, count)) if value == cell => *count += 1,
+            _ => runs.push((*cell, 1)),
+        }
+    }
+    let tokens: Vec<String> = runs
+        .into_iter()
+        .map(|(value, count)| {
+            let value = value.map_or_else(|| EMPTY_TILE.to_string(), |index| index.to_string());
+            if count == 1 {
+                value
+            } else {
+                format!("{}{}{}", value, RUN_SEPARATOR, count)
+            }
+        })
+        .collect();
+    tokens.join(" ")
+}
+
+/// Reads a row written by `encode_tile_row` back as (column, index) for every cell that isn't
+/// empty. `name` and `row` only appear in errors.
+fn decode_tile_row(name: &str, row: usize, line: &str) -> Result<Vec<(i64, u32)>, LevelIoError> {
+    let mut cells = Vec::new();
+    let mut col = 0;
+    for token in line.split_whitespace() {
+        let bad_token = || {
+            invalid(format!(
+                "{} row {} has \"{}\", which isn't a number, {} or number{}count",
+                name,
+                row + 1,
+                token,
+                EMPTY_TILE,
+                RUN_SEPARATOR
+            ))
+        };
+        let (value, count) = match token.split_once(RUN_SEPARATOR) {
+            Some((value, count)) => match count.parse::<u32>() {
+                Ok(count) if count > 0 => (value, count as i64),
+                _ => return Err(bad_token()),
+            },
+            None => (token, 1),
+        };
+        let value = match value {
+            EMPTY_TILE => None,
+            index => Some(index.parse::<u32>().map_err(|_| bad_token())?),
+        };
+        if let Some(index) = value {
+            if col + count > MAX_FILLED_COLUMN {
+                return Err(invalid(format!(
+                    "{} row {} fills cells past column {}",
+                    name,
+                    row + 1,
+                    MAX_FILLED_COLUMN
+                )));
+            }
+            cells.extend((col..col + count).map(|col| (col, index)));
+        }
+        col += count;
+    }
+    Ok(cells)
+}
+
+impl Level {
+    fn text_tile_rows(
+        &self,
+        tiles: &HashMap<HashableVec2, TileIndex>,
+        bounds: LevelBounds,
+    ) -> Vec<String> {
+        let columns = self.spritesheet_info.num_rows as u32;
+        let mut grid = vec![vec![None; bounds.width() as usize]; bounds.height() as usize];
+        for (point, tile) in tiles {
+            let (col, row) = bounds.to_cell(point);
+            grid[row as usize][col as usize] = Some(tile.row as u32 * columns + tile.col as u32);
+        }
+        grid.iter().map(|row| encode_tile_row(row)).collect()
+    }
+
+    fn text_int_grid_rows(cells: &HashMap<HashableVec2, u32>, bounds: LevelBounds) -> Vec<String> {
+        let mut grid = vec![vec![None; bounds.width() as usize]; bounds.height() as usize];
+        for (point, value) in cells {
+            let (col, row) = bounds.to_cell(point);
+            grid[row as usize][col as usize] = Some(*value);
+        }
+        grid.iter().map(|row| encode_tile_row(row)).collect()
+    }
+
+    /// Serializes the level as it would be saved to `level_path`, which the spritesheet path is
+    /// recorded relative to.
+    pub fn write_text(&self, level_path: &Path) -> Result<String, LevelIoError> {
+        let level_dir = level_path.parent().unwrap_or_else(|| Path::new(""));
+        let bounds = self.bounds();
+        check_grid_size(bounds)?;
+        // `read_text` wouldn't fill cells past this, so the level couldn't be opened again
+        if bounds.width() > MAX_FILLED_COLUMN {
+            return Err(LevelIoError::TooLarge(bounds));
+        }
+        let symbols: Vec<char> = COLLISION_SYMBOLS.chars().collect();
+        if self.collision_kinds.len() > symbols.len() {
+            return Err(invalid(format!(
+                "the text format holds at most {} collision kinds",
+                symbols.len()
+            )));
+        }
+        let mut collision = vec![vec![EMPTY; bounds.width() as usize]; bounds.height() as usize];
+        let mut collision_shapes = Vec::new();
+        for (point, cell) in sorted_collision(&self.collision) {
+            let (col, row) = bounds.to_cell(point);
+            collision[row as usize][col as usize] = symbols[cell.kind as usize];
+            if !cell.shape.is_full() {
+                collision_shapes.push(TextCollisionShape {
+                    x: point.x,
+                    y: point.y,
+                    shape: cell.shape.clone(),
+                });
+            }
+        }
+        let level = TextLevel {
+            format_version: TEXT_FORMAT_VERSION,
+            spritesheet_info: self.spritesheet_info,
+            spritesheet: self.spritesheet.as_ref().map(|source| TextSpritesheet {
+                path: relative_path(level_dir, &source.path),
+                width: source.width,
+                height: source.height,
+                hash: source.hash.map(|hash| format!("{:016x}", hash)),
+            }),
+            origin: HashableVec2 {
+                x: bounds.min_x,
+                y: bounds.max_y,
+            },
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| TextLayer {
+                    name: layer.name.clone(),
+                    rows: self.text_tile_rows(&layer.tiles, bounds),
+                })
+                .collect(),
+            background: Vec::new(),
+            foreground: Vec::new(),
+            collision_kinds: self
+                .collision_kinds
+                .iter()
+                .zip(symbols)
+                .map(|(kind, symbol)| TextCollisionKind {
+                    name: kind.name.clone(),
+                    color: kind.color,
+                    symbol,
+                })
+                .collect(),
+            collision: collision
+                .into_iter()
+                .map(|row| {
+                    let row: String = row.into_iter().collect();
+                    row.trim_end_matches(EMPTY).to_string()
+                })
+                .collect(),
+            collision_shapes,
+            int_grids: self
+                .int_grids
+                .iter()
+                .map(|layer| TextIntGrid {
+                    name: layer.name.clone(),
+                    values: layer.values.clone(),
+                    rows: Level::text_int_grid_rows(&layer.cells, bounds),
+                })
+                .collect(),
+            entities: sorted_entities(&self.entities)
+                .into_iter()
+                .map(|(point, label)| TextEntity {
+                    x: point.x,
+                    y: point.y,
+                    label: label.clone(),
+                })
+                .collect(),
+        };
+        let mut text =
+            ron::ser::to_string_pretty(&level, ron::ser::PrettyConfig::new()).map_err(invalid)?;
+        text.push('\n');
+        Ok(text)
+    }
+
+    /// Reads a level written by `write_text` to `level_path`. Like `import_json`, its spritesheet
+    /// settings always replace `base`'s, but `base`'s spritesheet is kept if it doesn't name one.
+    pub fn read_text(text: &str, level_path: &Path, base: Level) -> Result<Level, LevelIoError> {
+        let level: TextLevel = ron::from_str(text).map_err(invalid)?;
+        if level.format_version > TEXT_FORMAT_VERSION {
+            return Err(LevelIoError::UnsupportedVersion(
+                level.format_version.try_into().unwrap_or(u16::MAX),
+            ));
+        }
+        check_spritesheet_info(&level.spritesheet_info)?;
+        let level_dir = level_path.parent().unwrap_or_else(|| Path::new(""));
+        let spritesheet = match level.spritesheet {
+            Some(sheet) => Some(SpritesheetSource {
+                path: level_dir.join(sheet.path),
+                width: sheet.width,
+                height: sheet.height,
+                hash: sheet
+                    .hash
+                    .and_then(|hash| u64::from_str_radix(&hash, 16).ok()),
+            }),
+            None => base.spritesheet,
+        };
+        let origin = level.origin;
+        let to_point = |col: i64, row: usize| HashableVec2 {
+            x: origin.x + col,
+            y: origin.y - row as i64,
+        };
+        let columns = level.spritesheet_info.num_rows as u32;
+        let read_tiles = |name: &str,
+                          rows: &[String]|
+         -> Result<HashMap<HashableVec2, TileIndex>, LevelIoError> {
+            let mut tiles = HashMap::new();
+            for (row, line) in rows.iter().enumerate() {
+                for (col, index) in decode_tile_row(name, row, line)? {
+                    let tile = u16::try_from(index % columns)
+                        .ok()
+                        .zip(u16::try_from(index / columns).ok())
+                        .map(|(col, row)| TileIndex { col, row })
+                        .ok_or_else(|| {
+                            invalid(format!("{} has an invalid tile index {}", name, index))
+                        })?;
+                    tiles.insert(to_point(col, row), tile);
+                }
+            }
+            Ok(tiles)
+        };
+        let layers = if level.format_version < 2 {
+            let [background, foreground] = DEFAULT_LAYER_NAMES;
+            vec![
+                TileLayer {
+                    name: background.to_string(),
+                    tiles: read_tiles("background", &level.background)?,
+                },
+                TileLayer {
+                    name: foreground.to_string(),
+                    tiles: read_tiles("foreground", &level.foreground)?,
+                },
+            ]
+        } else {
+            level
+                .layers
+                .iter()
+                .map(|layer| {
+                    Ok(TileLayer {
+                        name: layer.name.clone(),
+                        tiles: read_tiles(&format!("layer \"{}\"", layer.name), &layer.rows)?,
+                    })
+                })
+                .collect::<Result<_, LevelIoError>>()?
+        };
+        let (collision_kinds, symbols): (Vec<CollisionKind>, Vec<char>) =
+            if level.format_version < 3 {
+                default_collision_kinds()
+                    .into_iter()
+                    .zip(COLLISION_SYMBOLS.chars())
+                    .unzip()
+            } else {
+                level
+                    .collision_kinds
+                    .into_iter()
+                    .map(|kind| (CollisionKind::new(kind.name, kind.color), kind.symbol))
+                    .unzip()
+            };
+        if symbols.contains(&EMPTY) {
+            return Err(invalid(format!(
+                "'{}' marks empty cells, it can't be a collision kind's symbol",
+                EMPTY
+            )));
+        }
+        let mut collision = HashMap::new();
+        for (row, line) in level.collision.iter().enumerate() {
+            for (col, cell) in line.chars().enumerate() {
+                if cell == EMPTY {
+                    continue;
+                }
+                let kind = symbols
+                    .iter()
+                    .position(|symbol| *symbol == cell)
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "collision row {} has '{}', which isn't {} or a kind's symbol",
+                            row + 1,
+                            cell,
+                            EMPTY
+                        ))
+                    })?;
+                collision.insert(to_point(col as i64, row), CollisionCell::full(kind as u16));
+            }
+        }
+        for shape in level.collision_shapes {
+            let cell = collision
+                .get_mut(&HashableVec2 {
+                    x: shape.x,
+                    y: shape.y,
+                })
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "({}, {}) has a collision shape but no collision",
+                        shape.x, shape.y
+                    ))
+                })?;
+            cell.shape = shape.shape;
+        }
+        let mut int_grids = Vec::with_capacity(level.int_grids.len());
+        for grid in level.int_grids {
+            let mut layer = IntGridLayer::new(grid.name);
+            let name = format!("int grid \"{}\"", layer.name);
+            for (row, line) in grid.rows.iter().enumerate() {
+                for (col, value) in decode_tile_row(&name, row, line)? {
+                    layer.cells.insert(to_point(col, row), value);
+                }
+            }
+            layer.values = grid.values;
+            int_grids.push(layer);
+        }
+        let entities = level
+            .entities
+            .into_iter()
+            .map(|entity| {
+                (
+                    HashableVec2 {
+                        x: entity.x,
+                        y: entity.y,
+                    },
+                    entity.label,
+                )
+            })
+            .collect();
+        let level = Level {
+            spritesheet_info: level.spritesheet_info,
+            spritesheet,
+            layers,
+            collision,
+            collision_kinds,
+            int_grids,
+            entities,
+            ..base
+        };
+        level.check_collision()?;
+        level.check_int_grids()?;
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{reinserted, sample_level, test_dir};
+
+    #[test]
+    fn round_trip() {
+        let dir = test_dir("text-round-trip");
+        let level = sample_level(&dir);
+        let level_path = dir.join("level.ron");
+        let text = level.write_text(&level_path).unwrap();
+        let read = Level::read_text(&text, &level_path, Level::default()).unwrap();
+        assert_eq!(read, level);
+    }
+
+    #[test]
+    fn empty_spritesheet_grid_is_corrupt() {
+        let dir = test_dir("text-empty-grid");
+        let level_path = dir.join("level.ron");
+        let text = sample_level(&dir).write_text(&level_path).unwrap();
+        let text = text.replacen("num_cols: 4", "num_cols: 0", 1);
+        assert!(matches!(
+            Level::read_text(&text, &level_path, Level::default()),
+            Err(LevelIoError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn wide_levels_reopen_or_are_refused() {
+        let dir = test_dir("text-wide");
+        let level_path = dir.join("level.ron");
+        let mut level = sample_level(&dir);
+        let tile = TileIndex { col: 1, row: 1 };
+        let point = |x| HashableVec2 { x, y: 0 };
+        level.layers[0].tiles = HashMap::from([(point(0), tile), (point(2_000_000), tile)]);
+        assert!(matches!(
+            level.write_text(&level_path),
+            Err(LevelIoError::TooLarge(_))
+        ));
+        let mut level = Level::default();
+        level.layers[0].tiles =
+            HashMap::from([(point(0), tile), (point(MAX_FILLED_COLUMN - 1), tile)]);
+        let text = level.write_text(&level_path).unwrap();
+        let read = Level::read_text(&text, &level_path, Level::default()).unwrap();
+        assert_eq!(read, level);
+    }
+
+    #[test]
+    fn far_apart_cells_are_refused() {
+        let dir = test_dir("text-far-apart");
+        let mut level = Level::default();
+        let point = |x, y| HashableVec2 { x, y };
+        level.entities = HashMap::from([
+            (point(i64::MIN, i64::MIN), "a".to_string()),
+            (point(i64::MAX, i64::MAX), "b".to_string()),
+        ]);
+        assert!(matches!(
+            level.write_text(&dir.join("level.ron")),
+            Err(LevelIoError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn writes_are_deterministic() {
+        let dir = test_dir("text-deterministic");
+        let level = sample_level(&dir);
+        let level_path = dir.join("level.ron");
+        let text = level.write_text(&level_path).unwrap();
+        assert_eq!(level.write_text(&level_path).unwrap(), text);
+        assert_eq!(reinserted(&level).write_text(&level_path).unwrap(), text);
+    }
+}
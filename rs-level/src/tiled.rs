@@ -0,0 +1,1487 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::file::{
+    check_grid_size, relative_path, sorted_collision, sorted_entities, LevelIoError,
+};
+use crate::{
+    CollisionCell, CollisionKind, CollisionShape, HashableVec2, Level, SpritesheetInfo,
+    SpritesheetSource, TileIndex, TileLayer,
+};
+
+/// Tiled version the exported files claim to be written by.
+const TILED_VERSION: &str = "1.9";
+/// Given in turn to collision kinds that imported object classes add to the level.
+const IMPORTED_KIND_COLORS: [[u8; 3]; 6] = [
+    [50, 205, 50],
+    [255, 215, 0],
+    [0, 206, 209],
+    [138, 43, 226],
+    [255, 105, 180],
+    [128, 128, 128],
+];
+/// Imported collision objects covering more cells than this are skipped rather than filled in.
+const MAX_OBJECT_CELLS: i64 = 1 << 20;
+
+/// The level mapped onto Tiled's bounded, y-down grid. Tile column 0 and row 0 sit at
+/// (`origin_x`, `origin_y`) in level coordinates, and both are written out as map properties so
+/// the sparse coordinates can be recovered.
+struct TiledMap {
+    width: i64,
+    height: i64,
+    origin_x: i64,
+    origin_y: i64,
+    tile_width: u32,
+    tile_height: u32,
+    columns: u32,
+    tile_count: u32,
+    image: Option<(String, u32, u32)>,
+    /// Name and gids of every tile layer, bottom first.
+    layers: Vec<(String, Vec<u32>)>,
+    /// Collision objects, each with the name of its kind.
+    collision: Vec<(CollisionObject, String)>,
+    /// Cell positions of entities, as (column, row), with their labels.
+    entities: Vec<(i64, i64, String)>,
+}
+
+/// A collision cell as a Tiled object, in pixels from the map's top left corner.
+enum CollisionObject {
+    Rectangle {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    /// Tiled gives polygon corners relative to the object's position, here the cell's top left
+    /// corner.
+    Polygon {
+        x: f64,
+        y: f64,
+        corners: Vec<(f64, f64)>,
+    },
+}
+
+impl TiledMap {
+    fn to_cell(&self, point: &HashableVec2) -> (i64, i64) {
+        (point.x - self.origin_x, self.origin_y - point.y)
+    }
+    /// Full and half cells stay rectangles, every other shape becomes a polygon.
+    fn collision_object(&self, point: &HashableVec2, shape: &CollisionShape) -> CollisionObject {
+        let (col, row) = self.to_cell(point);
+        let (tile_width, tile_height) = (self.tile_width as f64, self.tile_height as f64);
+        let (x, y) = (col as f64 * tile_width, row as f64 * tile_height);
+        // cell space has y up, Tiled has y down
+        let corners: Vec<(f64, f64)> = shape
+            .outline()
+            .into_iter()
+            .map(|[cx, cy]| (cx as f64 * tile_width, (1.0 - cy as f64) * tile_height))
+            .collect();
+        match shape {
+            CollisionShape::Full
+            | CollisionShape::HalfTop
+            | CollisionShape::HalfBottom
+            | CollisionShape::HalfLeft
+            | CollisionShape::HalfRight => {
+                let left = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+                let right = corners.iter().map(|c| c.0).fold(0.0, f64::max);
+                let top = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+                let bottom = corners.iter().map(|c| c.1).fold(0.0, f64::max);
+                CollisionObject::Rectangle {
+                    x: x + left,
+                    y: y + top,
+                    width: right - left,
+                    height: bottom - top,
+                }
+            }
+            _ => CollisionObject::Polygon { x, y, corners },
+        }
+    }
+    fn layer_data(&self, tiles: &HashMap<HashableVec2, TileIndex>) -> Vec<u32> {
+        let mut data = vec![0; (self.width * self.height) as usize];
+        for (point, tile) in tiles {
+            let (col, row) = self.to_cell(point);
+            // gids start at 1 for the first tileset, 0 means no tile
+            data[(row * self.width + col) as usize] =
+                1 + tile.row as u32 * self.columns + tile.col as u32;
+        }
+        data
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Level {
+    fn tiled_map(&self, map_path: &Path) -> TiledMap {
+        let bounds = self.bounds();
+        let columns = self.spritesheet_info.num_rows as u32;
+        let rows = self.spritesheet_info.num_cols as u32;
+        let (tile_width, tile_height) = self.tile_pixel_size();
+        let map_dir = map_path.parent().unwrap_or_else(|| Path::new(""));
+        let image = self.spritesheet.as_ref().map(|source| {
+            (
+                relative_path(map_dir, &source.path),
+                source.width,
+                source.height,
+            )
+        });
+        let mut map = TiledMap {
+            width: bounds.width(),
+            height: bounds.height(),
+            origin_x: bounds.min_x,
+            origin_y: bounds.max_y,
+            tile_width,
+            tile_height,
+            columns,
+            tile_count: columns * rows,
+            image,
+            layers: Vec::new(),
+            collision: Vec::new(),
+            entities: Vec::new(),
+        };
+        map.layers = self
+            .layers
+            .iter()
+            .map(|layer| (layer.name.clone(), map.layer_data(&layer.tiles)))
+            .collect();
+        // in the order the other formats write them, so object ids don't change between exports
+        map.collision = sorted_collision(&self.collision)
+            .into_iter()
+            .map(|(point, cell)| {
+                let name = self
+                    .collision_kinds
+                    .get(cell.kind as usize)
+                    .map(|kind| kind.name.clone())
+                    .unwrap_or_default();
+                (map.collision_object(point, &cell.shape), name)
+            })
+            .collect();
+        map.entities = sorted_entities(&self.entities)
+            .into_iter()
+            .map(|(point, label)| {
+                let (col, row) = map.to_cell(point);
+                (col, row, label.clone())
+            })
+            .collect();
+        map
+    }
+
+    /// Writes the level as a Tiled map, TMJ when `path` ends in `.tmj` or `.json` and TMX
+    /// otherwise. Every layer becomes a tile layer, collision an object group of rectangles and
+    /// polygons in the shape of each cell, whose class is their collision kind, and entities an
+    /// object group of points named by their labels. Int grids have no counterpart in Tiled and
+    /// are left out. Returns a line for everything in the level the map couldn't hold.
+    pub fn export_tiled(&self, path: &Path) -> Result<Vec<String>, LevelIoError> {
+        // every layer is stored as a grid over the whole level
+        check_grid_size(self.bounds())?;
+        let map = self.tiled_map(path);
+        let is_json = matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("tmj" | "json")
+        );
+        let mut buffer = Vec::new();
+        if is_json {
+            serde_json::to_writer_pretty(&mut buffer, &tmj(&map))?;
+        } else {
+            write_tmx(&map, &mut buffer)?;
+        }
+        std::fs::write(path, buffer)?;
+        let report = self
+            .int_grids
+            .iter()
+            .map(|layer| {
+                format!(
+                    "Int grid \"{}\" was left out, Tiled maps have no int grids",
+                    layer.name
+                )
+            })
+            .collect();
+        Ok(report)
+    }
+}
+
+fn write_tmx(map: &TiledMap, out: &mut impl Write) -> std::io::Result<()> {
+    // tile layers take the first ids, then collision and entities
+    let collision_id = map.layers.len() + 1;
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<map version="{}" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="{}" nextobjectid="{}">"#,
+        TILED_VERSION,
+        map.width,
+        map.height,
+        map.tile_width,
+        map.tile_height,
+        collision_id + 2,
+        map.collision.len() + map.entities.len() + 1
+    )?;
+    writeln!(out, " <properties>")?;
+    writeln!(
+        out,
+        r#"  <property name="origin_x" type="int" value="{}"/>"#,
+        map.origin_x
+    )?;
+    writeln!(
+        out,
+        r#"  <property name="origin_y" type="int" value="{}"/>"#,
+        map.origin_y
+    )?;
+    writeln!(out, " </properties>")?;
+    writeln!(
+        out,
+        r#" <tileset firstgid="1" name="spritesheet" tilewidth="{}" tileheight="{}" tilecount="{}" columns="{}">"#,
+        map.tile_width, map.tile_height, map.tile_count, map.columns
+    )?;
+    if let Some((source, width, height)) = &map.image {
+        writeln!(
+            out,
+            r#"  <image source="{}" width="{}" height="{}"/>"#,
+            escape_xml(source),
+            width,
+            height
+        )?;
+    }
+    writeln!(out, " </tileset>")?;
+    for (id, (name, data)) in (1..).zip(&map.layers) {
+        writeln!(
+            out,
+            r#" <layer id="{}" name="{}" width="{}" height="{}">"#,
+            id,
+            escape_xml(name),
+            map.width,
+            map.height
+        )?;
+        writeln!(out, r#"  <data encoding="csv">"#)?;
+        let rows: Vec<String> = data
+            .chunks(map.width as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|gid| gid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        writeln!(out, "{}", rows.join(",\n"))?;
+        writeln!(out, "  </data>")?;
+        writeln!(out, " </layer>")?;
+    }
+    let mut object_id = 1;
+    writeln!(
+        out,
+        r#" <objectgroup id="{}" name="collision">"#,
+        collision_id
+    )?;
+    for (object, kind) in &map.collision {
+        match object {
+            CollisionObject::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => writeln!(
+                out,
+                r#"  <object id="{}" class="{}" x="{}" y="{}" width="{}" height="{}"/>"#,
+                object_id,
+                escape_xml(kind),
+                x,
+                y,
+                width,
+                height
+            )?,
+            CollisionObject::Polygon { x, y, corners } => {
+                writeln!(
+                    out,
+                    r#"  <object id="{}" class="{}" x="{}" y="{}">"#,
+                    object_id,
+                    escape_xml(kind),
+                    x,
+                    y
+                )?;
+                let points: Vec<String> = corners
+                    .iter()
+                    .map(|(x, y)| format!("{},{}", x, y))
+                    .collect();
+                writeln!(out, r#"   <polygon points="{}"/>"#, points.join(" "))?;
+                writeln!(out, "  </object>")?;
+            }
+        }
+        object_id += 1;
+    }
+    writeln!(out, " </objectgroup>")?;
+    writeln!(
+        out,
+        r#" <objectgroup id="{}" name="entities">"#,
+        collision_id + 1
+    )?;
+    for (col, row, label) in &map.entities {
+        writeln!(
+            out,
+            r#"  <object id="{}" name="{}" x="{}" y="{}">"#,
+            object_id,
+            escape_xml(label),
+            (*col as f64 + 0.5) * map.tile_width as f64,
+            (*row as f64 + 0.5) * map.tile_height as f64
+        )?;
+        writeln!(out, "   <point/>")?;
+        writeln!(out, "  </object>")?;
+        object_id += 1;
+    }
+    writeln!(out, " </objectgroup>")?;
+    writeln!(out, "</map>")?;
+    Ok(())
+}
+
+fn tmj(map: &TiledMap) -> serde_json::Value {
+    let mut object_id = 1;
+    let mut collision_objects = Vec::with_capacity(map.collision.len());
+    for (object, kind) in &map.collision {
+        collision_objects.push(match object {
+            CollisionObject::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => json!({
+                "id": object_id,
+                "name": "",
+                "class": kind,
+                "x": x,
+                "y": y,
+                "width": width,
+                "height": height,
+                "rotation": 0,
+                "visible": true,
+            }),
+            CollisionObject::Polygon { x, y, corners } => json!({
+                "id": object_id,
+                "name": "",
+                "class": kind,
+                "x": x,
+                "y": y,
+                "width": 0,
+                "height": 0,
+                "rotation": 0,
+                "visible": true,
+                "polygon": corners
+                    .iter()
+                    .map(|(x, y)| json!({ "x": x, "y": y }))
+                    .collect::<Vec<_>>(),
+            }),
+        });
+        object_id += 1;
+    }
+    let mut entity_objects = Vec::with_capacity(map.entities.len());
+    for (col, row, label) in &map.entities {
+        entity_objects.push(json!({
+            "id": object_id,
+            "name": label,
+            "x": (*col as f64 + 0.5) * map.tile_width as f64,
+            "y": (*row as f64 + 0.5) * map.tile_height as f64,
+            "width": 0,
+            "height": 0,
+            "rotation": 0,
+            "visible": true,
+            "point": true,
+        }));
+        object_id += 1;
+    }
+    let mut tileset = json!({
+        "firstgid": 1,
+        "name": "spritesheet",
+        "tilewidth": map.tile_width,
+        "tileheight": map.tile_height,
+        "tilecount": map.tile_count,
+        "columns": map.columns,
+        "margin": 0,
+        "spacing": 0,
+    });
+    if let Some((source, width, height)) = &map.image {
+        tileset["image"] = json!(source);
+        tileset["imagewidth"] = json!(width);
+        tileset["imageheight"] = json!(height);
+    }
+    let tile_layer = |id: usize, (name, data): &(String, Vec<u32>)| {
+        json!({
+            "id": id,
+            "name": name,
+            "type": "tilelayer",
+            "x": 0,
+            "y": 0,
+            "width": map.width,
+            "height": map.height,
+            "opacity": 1,
+            "visible": true,
+            "data": data,
+        })
+    };
+    // tile layers take the first ids, then collision and entities
+    let collision_id = map.layers.len() + 1;
+    let mut layers: Vec<serde_json::Value> = (1..)
+        .zip(&map.layers)
+        .map(|(id, layer)| tile_layer(id, layer))
+        .collect();
+    layers.push(json!({
+        "id": collision_id,
+        "name": "collision",
+        "type": "objectgroup",
+        "draworder": "topdown",
+        "opacity": 1,
+        "visible": true,
+        "x": 0,
+        "y": 0,
+        "objects": collision_objects,
+    }));
+    layers.push(json!({
+        "id": collision_id + 1,
+        "name": "entities",
+        "type": "objectgroup",
+        "draworder": "topdown",
+        "opacity": 1,
+        "visible": true,
+        "x": 0,
+        "y": 0,
+        "objects": entity_objects,
+    }));
+    json!({
+        "type": "map",
+        "version": TILED_VERSION,
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "width": map.width,
+        "height": map.height,
+        "tilewidth": map.tile_width,
+        "tileheight": map.tile_height,
+        "infinite": false,
+        "nextlayerid": collision_id + 2,
+        "nextobjectid": object_id,
+        "properties": [
+            { "name": "origin_x", "type": "int", "value": map.origin_x },
+            { "name": "origin_y", "type": "int", "value": map.origin_y },
+        ],
+        "tilesets": [tileset],
+        "layers": layers,
+    })
+}
+
+/// High bits of a gid that Tiled uses for flipping and rotation rather than tile ids.
+const GID_FLAG_MASK: u32 = 0xf000_0000;
+
+struct ImportedTileset {
+    first_gid: u32,
+    tile_width: u32,
+    columns: u32,
+    tile_count: u32,
+    /// Path of the image, resolved against the map's directory, and its size.
+    image: Option<(PathBuf, u32, u32)>,
+    has_spacing: bool,
+}
+
+enum ObjectShape {
+    Rectangle,
+    Point,
+    Tile,
+    /// Corners relative to the object's position.
+    Polygon(Vec<(f64, f64)>),
+    Other(&'static str),
+}
+
+struct ImportedObject {
+    name: String,
+    /// Tiled 1.9 renamed an object's `type` to `class`, either is read.
+    class: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    shape: ObjectShape,
+}
+
+impl ImportedObject {
+    /// The corners of rectangles with an area and of polygons, in pixels from the map's top
+    /// left corner.
+    fn corners(&self) -> Option<Vec<(f64, f64)>> {
+        let (x, y) = (self.x, self.y);
+        match &self.shape {
+            ObjectShape::Rectangle if self.width > 0.0 && self.height > 0.0 => {
+                let (right, bottom) = (x + self.width, y + self.height);
+                Some(vec![(x, bottom), (right, bottom), (right, y), (x, y)])
+            }
+            ObjectShape::Polygon(corners) if corners.len() >= 3 => Some(
+                corners
+                    .iter()
+                    .map(|(corner_x, corner_y)| (x + corner_x, y + corner_y))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Left, top, right and bottom edges of `corners`.
+fn bounding_box(corners: &[(f64, f64)]) -> (f64, f64, f64, f64) {
+    corners.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(left, top, right, bottom), (x, y)| {
+            (left.min(*x), top.min(*y), right.max(*x), bottom.max(*y))
+        },
+    )
+}
+
+/// The preset with the same corners as `outline`, in any order, or else a polygon of it. Corners
+/// are clamped to the cell, as `Level::check_collision` requires.
+fn imported_shape(outline: Vec<[f32; 2]>) -> CollisionShape {
+    let outline: Vec<[f32; 2]> = outline
+        .into_iter()
+        .map(|corner| corner.map(|coordinate| coordinate.clamp(0.0, 1.0)))
+        .collect();
+    let same_corner =
+        |a: &[f32; 2], b: &[f32; 2]| (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4;
+    CollisionShape::PRESETS
+        .iter()
+        .find(|preset| {
+            let corners = preset.outline();
+            corners.len() == outline.len()
+                && corners
+                    .iter()
+                    .all(|corner| outline.iter().any(|other| same_corner(corner, other)))
+        })
+        .cloned()
+        .unwrap_or(CollisionShape::Polygon(outline))
+}
+
+enum ImportedLayer {
+    /// Non-empty cells as (column, row, gid).
+    Tiles(String, Vec<(i64, i64, u32)>),
+    Objects(String, Vec<ImportedObject>),
+    Unsupported(String, &'static str),
+}
+
+/// The parts of a TMX or TMJ map the importer understands, read into one shape so the mapping
+/// onto the level only has to be written once.
+struct ImportedMap {
+    orientation: String,
+    height: i64,
+    tile_width: u32,
+    tile_height: u32,
+    origin: Option<(i64, i64)>,
+    tilesets: Vec<ImportedTileset>,
+    layers: Vec<ImportedLayer>,
+    /// Anything skipped while reading, reported ahead of what the level leaves out.
+    report: Vec<String>,
+}
+
+fn invalid(why: impl std::fmt::Display) -> LevelIoError {
+    LevelIoError::InvalidTiledMap(why.to_string())
+}
+
+/// Turns the contents of a layer's (or chunk's) data into gids, row by row.
+fn decode_tile_data(
+    data: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> Result<Vec<u32>, LevelIoError> {
+    match encoding {
+        Some("csv") => data
+            .split(',')
+            .map(|gid| gid.trim())
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse::<u32>().map_err(invalid))
+            .collect(),
+        Some("base64") => {
+            let text: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+            let bytes = base64::decode(text).map_err(invalid)?;
+            let bytes = match compression {
+                None | Some("") => bytes,
+                Some("zlib") => {
+                    let mut inflated = Vec::new();
+                    flate2::read::ZlibDecoder::new(&bytes[..]).read_to_end(&mut inflated)?;
+                    inflated
+                }
+                Some("gzip") => {
+                    let mut inflated = Vec::new();
+                    flate2::read::GzDecoder::new(&bytes[..]).read_to_end(&mut inflated)?;
+                    inflated
+                }
+                Some(other) => {
+                    return Err(invalid(format!(
+                        "tile data compressed with {} is not supported",
+                        other
+                    )))
+                }
+            };
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|gid| u32::from_le_bytes(gid.try_into().unwrap()))
+                .collect())
+        }
+        other => Err(invalid(format!(
+            "tile data encoding {} is not supported",
+            other.unwrap_or("none")
+        ))),
+    }
+}
+
+/// Lays decoded gids out over a `width` wide region whose top left cell is (`x`, `y`), dropping
+/// empty cells.
+fn place_gids(
+    gids: Vec<u32>,
+    x: i64,
+    y: i64,
+    width: i64,
+) -> Result<Vec<(i64, i64, u32)>, LevelIoError> {
+    if width <= 0 {
+        return Ok(Vec::new());
+    }
+    gids.into_iter()
+        .enumerate()
+        .filter(|(_, gid)| *gid != 0)
+        .map(|(i, gid)| {
+            x.checked_add(i as i64 % width)
+                .zip(y.checked_add(i as i64 / width))
+                .map(|(col, row)| (col, row, gid))
+                .ok_or_else(|| invalid("a tile layer reaches past the largest map coordinates"))
+        })
+        .collect()
+}
+
+fn xml_attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Option<T> {
+    node.attribute(name).and_then(|value| value.parse().ok())
+}
+
+fn xml_child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn read_tsx_tileset(
+    node: roxmltree::Node,
+    first_gid: u32,
+    base_dir: &Path,
+) -> Result<ImportedTileset, LevelIoError> {
+    let image = xml_child(node, "image").and_then(|image| {
+        Some((
+            base_dir.join(image.attribute("source")?),
+            xml_attribute(image, "width").unwrap_or(0),
+            xml_attribute(image, "height").unwrap_or(0),
+        ))
+    });
+    Ok(ImportedTileset {
+        first_gid,
+        tile_width: xml_attribute(node, "tilewidth")
+            .ok_or_else(|| invalid("tileset has no tilewidth"))?,
+        columns: xml_attribute(node, "columns").unwrap_or(0),
+        tile_count: xml_attribute(node, "tilecount").unwrap_or(0),
+        image,
+        has_spacing: xml_attribute::<u32>(node, "spacing").unwrap_or(0) != 0
+            || xml_attribute::<u32>(node, "margin").unwrap_or(0) != 0,
+    })
+}
+
+fn read_external_tileset(path: &Path, first_gid: u32) -> Result<ImportedTileset, LevelIoError> {
+    let text = std::fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let is_json = matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("tsj" | "json")
+    );
+    if is_json {
+        let value: serde_json::Value = serde_json::from_str(&text)?;
+        read_tmj_tileset(&value, first_gid, base_dir)
+    } else {
+        let document = roxmltree::Document::parse(&text).map_err(invalid)?;
+        read_tsx_tileset(document.root_element(), first_gid, base_dir)
+    }
+}
+
+fn read_tmx_object(node: roxmltree::Node) -> ImportedObject {
+    let shape = if node.attribute("gid").is_some() {
+        ObjectShape::Tile
+    } else if xml_child(node, "point").is_some() {
+        ObjectShape::Point
+    } else if xml_child(node, "ellipse").is_some() {
+        ObjectShape::Other("ellipse")
+    } else if let Some(polygon) = xml_child(node, "polygon") {
+        let corners = polygon
+            .attribute("points")
+            .unwrap_or("")
+            .split_whitespace()
+            .filter_map(|point| {
+                let (x, y) = point.split_once(',')?;
+                Some((x.parse().ok()?, y.parse().ok()?))
+            })
+            .collect();
+        ObjectShape::Polygon(corners)
+    } else if xml_child(node, "polyline").is_some() {
+        ObjectShape::Other("polyline")
+    } else if xml_child(node, "text").is_some() {
+        ObjectShape::Other("text")
+    } else {
+        ObjectShape::Rectangle
+    };
+    ImportedObject {
+        name: node.attribute("name").unwrap_or("").to_string(),
+        class: node
+            .attribute("class")
+            .or_else(|| node.attribute("type"))
+            .unwrap_or("")
+            .to_string(),
+        x: xml_attribute(node, "x").unwrap_or(0.0),
+        y: xml_attribute(node, "y").unwrap_or(0.0),
+        width: xml_attribute(node, "width").unwrap_or(0.0),
+        height: xml_attribute(node, "height").unwrap_or(0.0),
+        shape,
+    }
+}
+
+fn read_tmx_layers(
+    node: roxmltree::Node,
+    layers: &mut Vec<ImportedLayer>,
+) -> Result<(), LevelIoError> {
+    for child in node.children().filter(|child| child.is_element()) {
+        let name = child.attribute("name").unwrap_or("").to_string();
+        match child.tag_name().name() {
+            "layer" => {
+                let data =
+                    xml_child(child, "data").ok_or_else(|| invalid("tile layer has no data"))?;
+                let encoding = data.attribute("encoding");
+                let compression = data.attribute("compression");
+                let mut cells = Vec::new();
+                let chunks: Vec<_> = data
+                    .children()
+                    .filter(|c| c.has_tag_name("chunk"))
+                    .collect();
+                if encoding.is_none() {
+                    // one <tile gid="..."/> per cell
+                    let gids = data
+                        .children()
+                        .filter(|c| c.has_tag_name("tile"))
+                        .map(|tile| xml_attribute(tile, "gid").unwrap_or(0))
+                        .collect();
+                    let width = xml_attribute(child, "width").unwrap_or(0);
+                    cells = place_gids(gids, 0, 0, width)?;
+                } else if chunks.is_empty() {
+                    let gids = decode_tile_data(data.text().unwrap_or(""), encoding, compression)?;
+                    let width = xml_attribute(child, "width").unwrap_or(0);
+                    cells = place_gids(gids, 0, 0, width)?;
+                } else {
+                    for chunk in chunks {
+                        let gids =
+                            decode_tile_data(chunk.text().unwrap_or(""), encoding, compression)?;
+                        cells.extend(place_gids(
+                            gids,
+                            xml_attribute(chunk, "x").unwrap_or(0),
+                            xml_attribute(chunk, "y").unwrap_or(0),
+                            xml_attribute(chunk, "width").unwrap_or(0),
+                        )?);
+                    }
+                }
+                layers.push(ImportedLayer::Tiles(name, cells));
+            }
+            "objectgroup" => {
+                let objects = child
+                    .children()
+                    .filter(|c| c.has_tag_name("object"))
+                    .map(read_tmx_object)
+                    .collect();
+                layers.push(ImportedLayer::Objects(name, objects));
+            }
+            "group" => read_tmx_layers(child, layers)?,
+            "imagelayer" => layers.push(ImportedLayer::Unsupported(name, "image layer")),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn read_tmx(text: &str, map_dir: &Path) -> Result<ImportedMap, LevelIoError> {
+    let document = roxmltree::Document::parse(text).map_err(invalid)?;
+    let map = document.root_element();
+    if !map.has_tag_name("map") {
+        return Err(invalid("not a Tiled map"));
+    }
+    let property = |name: &str| -> Option<i64> {
+        let properties = xml_child(map, "properties")?;
+        let property = properties
+            .children()
+            .find(|p| p.has_tag_name("property") && p.attribute("name") == Some(name))?;
+        xml_attribute(property, "value")
+    };
+    let origin = property("origin_x").zip(property("origin_y"));
+    let mut tilesets = Vec::new();
+    for tileset in map.children().filter(|c| c.has_tag_name("tileset")) {
+        let first_gid = xml_attribute(tileset, "firstgid").unwrap_or(1);
+        tilesets.push(match tileset.attribute("source") {
+            Some(source) => read_external_tileset(&map_dir.join(source), first_gid)?,
+            None => read_tsx_tileset(tileset, first_gid, map_dir)?,
+        });
+    }
+    let mut layers = Vec::new();
+    read_tmx_layers(map, &mut layers)?;
+    Ok(ImportedMap {
+        orientation: map
+            .attribute("orientation")
+            .unwrap_or("orthogonal")
+            .to_string(),
+        height: xml_attribute(map, "height").unwrap_or(0),
+        tile_width: xml_attribute(map, "tilewidth")
+            .ok_or_else(|| invalid("map has no tilewidth"))?,
+        tile_height: xml_attribute(map, "tileheight")
+            .ok_or_else(|| invalid("map has no tileheight"))?,
+        origin,
+        tilesets,
+        layers,
+        report: Vec::new(),
+    })
+}
+
+fn json_u32(value: &serde_json::Value, key: &str) -> Option<u32> {
+    value.get(key)?.as_u64()?.try_into().ok()
+}
+
+fn json_i64(value: &serde_json::Value, key: &str) -> Option<i64> {
+    value.get(key)?.as_i64()
+}
+
+fn json_str<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a str> {
+    value.get(key)?.as_str()
+}
+
+fn read_tmj_tileset(
+    value: &serde_json::Value,
+    first_gid: u32,
+    base_dir: &Path,
+) -> Result<ImportedTileset, LevelIoError> {
+    let image = json_str(value, "image").map(|source| {
+        (
+            base_dir.join(source),
+            json_u32(value, "imagewidth").unwrap_or(0),
+            json_u32(value, "imageheight").unwrap_or(0),
+        )
+    });
+    Ok(ImportedTileset {
+        first_gid,
+        tile_width: json_u32(value, "tilewidth")
+            .ok_or_else(|| invalid("tileset has no tilewidth"))?,
+        columns: json_u32(value, "columns").unwrap_or(0),
+        tile_count: json_u32(value, "tilecount").unwrap_or(0),
+        image,
+        has_spacing: json_u32(value, "spacing").unwrap_or(0) != 0
+            || json_u32(value, "margin").unwrap_or(0) != 0,
+    })
+}
+
+/// TMJ tile data is either an array of gids or an encoded string. Array entries that aren't gids
+/// are left empty and counted in `skipped`.
+fn read_tmj_data(
+    value: &serde_json::Value,
+    layer: &serde_json::Value,
+    skipped: &mut usize,
+) -> Result<Vec<u32>, LevelIoError> {
+    match value {
+        serde_json::Value::Array(gids) => Ok(gids
+            .iter()
+            .map(|gid| {
+                gid.as_u64()
+                    .and_then(|gid| u32::try_from(gid).ok())
+                    .unwrap_or_else(|| {
+                        *skipped += 1;
+                        0
+                    })
+            })
+            .collect()),
+        serde_json::Value::String(data) => decode_tile_data(
+            data,
+            json_str(layer, "encoding"),
+            json_str(layer, "compression"),
+        ),
+        _ => Err(invalid("tile layer has no data")),
+    }
+}
+
+fn read_tmj_object(value: &serde_json::Value) -> ImportedObject {
+    let flag = |key: &str| value.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    let shape = if value.get("gid").is_some() {
+        ObjectShape::Tile
+    } else if flag("point") {
+        ObjectShape::Point
+    } else if flag("ellipse") {
+        ObjectShape::Other("ellipse")
+    } else if let Some(polygon) = value.get("polygon").and_then(|p| p.as_array()) {
+        let corners = polygon
+            .iter()
+            .filter_map(|point| Some((point.get("x")?.as_f64()?, point.get("y")?.as_f64()?)))
+            .collect();
+        ObjectShape::Polygon(corners)
+    } else if value.get("polyline").is_some() {
+        ObjectShape::Other("polyline")
+    } else if value.get("text").is_some() {
+        ObjectShape::Other("text")
+    } else {
+        ObjectShape::Rectangle
+    };
+    let number = |key: &str| value.get(key).and_then(|v| v.as_f64()).unwrap_or(0.0);
+    ImportedObject {
+        name: json_str(value, "name").unwrap_or("").to_string(),
+        class: json_str(value, "class")
+            .or_else(|| json_str(value, "type"))
+            .unwrap_or("")
+            .to_string(),
+        x: number("x"),
+        y: number("y"),
+        width: number("width"),
+        height: number("height"),
+        shape,
+    }
+}
+
+fn read_tmj_layers(
+    values: &[serde_json::Value],
+    layers: &mut Vec<ImportedLayer>,
+    report: &mut Vec<String>,
+) -> Result<(), LevelIoError> {
+    for layer in values {
+        let name = json_str(layer, "name").unwrap_or("").to_string();
+        match json_str(layer, "type") {
+            Some("tilelayer") => {
+                let mut cells = Vec::new();
+                let mut skipped = 0;
+                if let Some(chunks) = layer.get("chunks").and_then(|c| c.as_array()) {
+                    for chunk in chunks {
+                        let data = chunk.get("data").unwrap_or(&serde_json::Value::Null);
+                        cells.extend(place_gids(
+                            read_tmj_data(data, layer, &mut skipped)?,
+                            json_i64(chunk, "x").unwrap_or(0),
+                            json_i64(chunk, "y").unwrap_or(0),
+                            json_i64(chunk, "width").unwrap_or(0),
+                        )?);
+                    }
+                } else {
+                    let data = layer.get("data").unwrap_or(&serde_json::Value::Null);
+                    cells = place_gids(
+                        read_tmj_data(data, layer, &mut skipped)?,
+                        0,
+                        0,
+                        json_i64(layer, "width").unwrap_or(0),
+                    )?;
+                }
+                if skipped > 0 {
+                    report.push(format!(
+                        "{} tiles in \"{}\" with invalid gids were skipped",
+                        skipped, name
+                    ));
+                }
+                layers.push(ImportedLayer::Tiles(name, cells));
+            }
+            Some("objectgroup") => {
+                let objects = layer
+                    .get("objects")
+                    .and_then(|o| o.as_array())
+                    .map(|objects| objects.iter().map(read_tmj_object).collect())
+                    .unwrap_or_default();
+                layers.push(ImportedLayer::Objects(name, objects));
+            }
+            Some("group") => {
+                if let Some(children) = layer.get("layers").and_then(|l| l.as_array()) {
+                    read_tmj_layers(children, layers, report)?;
+                }
+            }
+            Some("imagelayer") => layers.push(ImportedLayer::Unsupported(name, "image layer")),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn read_tmj(text: &str, map_dir: &Path) -> Result<ImportedMap, LevelIoError> {
+    let map: serde_json::Value = serde_json::from_str(text)?;
+    let property = |name: &str| -> Option<i64> {
+        map.get("properties")?
+            .as_array()?
+            .iter()
+            .find(|p| json_str(p, "name") == Some(name))?
+            .get("value")?
+            .as_i64()
+    };
+    let origin = property("origin_x").zip(property("origin_y"));
+    let mut tilesets = Vec::new();
+    for tileset in map
+        .get("tilesets")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let first_gid = json_u32(tileset, "firstgid").unwrap_or(1);
+        tilesets.push(match json_str(tileset, "source") {
+            Some(source) => read_external_tileset(&map_dir.join(source), first_gid)?,
+            None => read_tmj_tileset(tileset, first_gid, map_dir)?,
+        });
+    }
+    let mut layers = Vec::new();
+    let mut report = Vec::new();
+    if let Some(values) = map.get("layers").and_then(|l| l.as_array()) {
+        read_tmj_layers(values, &mut layers, &mut report)?;
+    }
+    Ok(ImportedMap {
+        orientation: json_str(&map, "orientation")
+            .unwrap_or("orthogonal")
+            .to_string(),
+        height: json_i64(&map, "height").unwrap_or(0),
+        tile_width: json_u32(&map, "tilewidth").ok_or_else(|| invalid("map has no tilewidth"))?,
+        tile_height: json_u32(&map, "tileheight")
+            .ok_or_else(|| invalid("map has no tileheight"))?,
+        origin,
+        tilesets,
+        layers,
+        report,
+    })
+}
+
+impl Level {
+    /// Imports an orthogonal Tiled map, TMJ when `path` ends in `.tmj` or `.json` and TMX
+    /// otherwise. The first tileset becomes the spritesheet and every tile layer a layer of the
+    /// same name. A layer or object group named "collision" fills collision, with each object's
+    /// class picking the collision kind of that name, and rectangles and polygons inside a
+    /// single cell giving it their shape. Point objects become entities. Also
+    /// returns a line for everything that couldn't be mapped.
+    pub fn import_tiled(path: &Path) -> Result<(Level, Vec<String>), LevelIoError> {
+        let text = std::fs::read_to_string(path)?;
+        let map_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let is_json = matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("tmj" | "json")
+        );
+        let map = if is_json {
+            read_tmj(&text, map_dir)?
+        } else {
+            read_tmx(&text, map_dir)?
+        };
+        if map.orientation != "orthogonal" {
+            return Err(invalid(format!(
+                "{} maps are not supported, only orthogonal ones",
+                map.orientation
+            )));
+        }
+        if map.tile_width == 0 || map.tile_height == 0 {
+            return Err(LevelIoError::Corrupt(format!(
+                "the map's tiles are {}x{} pixels",
+                map.tile_width, map.tile_height
+            )));
+        }
+        let mut report = map.report;
+        let tileset = map
+            .tilesets
+            .first()
+            .ok_or_else(|| invalid("the map has no tilesets"))?;
+        if tileset.columns == 0 || tileset.image.is_none() {
+            return Err(invalid(
+                "the first tileset is an image collection, it needs a single spritesheet image",
+            ));
+        }
+        if tileset.has_spacing {
+            report
+                .push("The first tileset uses spacing or margins, tiles may be offset".to_string());
+        }
+        for extra in &map.tilesets[1..] {
+            report.push(format!(
+                "Tiles from the tileset starting at gid {} were skipped, only the first tileset is used",
+                extra.first_gid
+            ));
+        }
+        let last_gid = map
+            .tilesets
+            .get(1)
+            .map(|next| next.first_gid)
+            .unwrap_or(u32::MAX);
+        let info = SpritesheetInfo {
+            sprite_size: tileset.tile_width.clamp(1, u16::MAX as u32) as u16,
+            num_rows: tileset.columns.clamp(1, u8::MAX as u32) as u8,
+            num_cols: (tileset.tile_count / tileset.columns).clamp(1, u8::MAX as u32) as u8,
+        };
+        if tileset.columns > u8::MAX as u32 || tileset.tile_count / tileset.columns > u8::MAX as u32
+        {
+            report.push(format!(
+                "The tileset grid is larger than {} tiles on a side and was clamped",
+                u8::MAX
+            ));
+        }
+        // without our own origin properties, put the bottom row of the map on y = 0
+        let (origin_x, origin_y) = map.origin.unwrap_or((0, map.height.saturating_sub(1)));
+        let to_point = |col: i64, row: i64| {
+            origin_x
+                .checked_add(col)
+                .zip(origin_y.checked_sub(row))
+                .map(|(x, y)| HashableVec2 { x, y })
+                .ok_or_else(|| invalid("the map reaches past the largest level coordinates"))
+        };
+        let to_cell = |x: f64, y: f64| {
+            (
+                (x / map.tile_width as f64).floor() as i64,
+                (y / map.tile_height as f64).floor() as i64,
+            )
+        };
+
+        let mut level = Level {
+            spritesheet_info: info,
+            layers: Vec::new(),
+            ..Level::default()
+        };
+        let mut flipped = 0;
+        let mut labels = HashSet::new();
+        for layer in map.layers {
+            match layer {
+                ImportedLayer::Tiles(name, cells) if name.eq_ignore_ascii_case("collision") => {
+                    for (col, row, _) in cells {
+                        level
+                            .collision
+                            .insert(to_point(col, row)?, CollisionCell::full(0));
+                    }
+                }
+                ImportedLayer::Tiles(name, cells) => {
+                    let mut layer = TileLayer::new(name);
+                    let mut foreign = 0;
+                    for (col, row, gid) in cells {
+                        if gid & GID_FLAG_MASK != 0 {
+                            flipped += 1;
+                        }
+                        let gid = gid & !GID_FLAG_MASK;
+                        if gid < tileset.first_gid || gid >= last_gid {
+                            foreign += 1;
+                            continue;
+                        }
+                        let id = gid - tileset.first_gid;
+                        let tile = TileIndex {
+                            col: (id % tileset.columns) as u16,
+                            row: (id / tileset.columns) as u16,
+                        };
+                        layer.tiles.insert(to_point(col, row)?, tile);
+                    }
+                    if foreign > 0 {
+                        report.push(format!(
+                            "{} tiles in \"{}\" from other tilesets were skipped",
+                            foreign, layer.name
+                        ));
+                    }
+                    level.layers.push(layer);
+                }
+                ImportedLayer::Objects(name, objects) if name.eq_ignore_ascii_case("collision") => {
+                    for object in objects {
+                        let kind = match level.imported_collision_kind(&object.class, &mut report) {
+                            Some(kind) => kind,
+                            None => {
+                                report.push(format!(
+                                    "Collision object \"{}\" was skipped, its kind \"{}\" doesn't fit after {} others",
+                                    object.name,
+                                    object.class,
+                                    level.collision_kinds.len()
+                                ));
+                                continue;
+                            }
+                        };
+                        let corners = object.corners();
+                        let (left, top, right, bottom) = match &corners {
+                            Some(corners) => bounding_box(corners),
+                            None => (
+                                object.x,
+                                object.y,
+                                object.x + object.width,
+                                object.y + object.height,
+                            ),
+                        };
+                        let (min_col, min_row) = to_cell(left, top);
+                        // objects ending exactly on a cell edge don't cover the next cell
+                        let (max_col, max_row) = if right > left && bottom > top {
+                            let (col, row) = to_cell(right, bottom);
+                            let col_end = right / map.tile_width as f64;
+                            let row_end = bottom / map.tile_height as f64;
+                            (
+                                if col_end.fract() == 0.0 {
+                                    col.saturating_sub(1).max(min_col)
+                                } else {
+                                    col
+                                },
+                                if row_end.fract() == 0.0 {
+                                    row.saturating_sub(1).max(min_row)
+                                } else {
+                                    row
+                                },
+                            )
+                        } else {
+                            (min_col, min_row)
+                        };
+                        let span = |min: i64, max: i64| max.checked_sub(min)?.checked_add(1);
+                        let cell_count = span(min_col, max_col)
+                            .zip(span(min_row, max_row))
+                            .and_then(|(cols, rows)| cols.checked_mul(rows));
+                        if cell_count
+                            .filter(|count| *count <= MAX_OBJECT_CELLS)
+                            .is_none()
+                        {
+                            report.push(format!(
+                                "Collision object \"{}\" was skipped, it covers more than {} cells",
+                                object.name, MAX_OBJECT_CELLS
+                            ));
+                            continue;
+                        }
+                        // an object inside one cell keeps its shape, larger ones fill every cell
+                        // they touch
+                        let shape = match corners {
+                            Some(corners) if min_col == max_col && min_row == max_row => {
+                                let outline = corners
+                                    .into_iter()
+                                    .map(|(x, y)| {
+                                        [
+                                            (x / map.tile_width as f64 - min_col as f64) as f32,
+                                            (1.0 - (y / map.tile_height as f64 - min_row as f64))
+                                                as f32,
+                                        ]
+                                    })
+                                    .collect();
+                                imported_shape(outline)
+                            }
+                            _ => {
+                                let shape = match object.shape {
+                                    ObjectShape::Polygon(_) => Some("polygon"),
+                                    ObjectShape::Other(shape) => Some(shape),
+                                    _ => None,
+                                };
+                                if let Some(shape) = shape {
+                                    report.push(format!(
+                                        "Collision {} \"{}\" was filled in as its bounding box",
+                                        shape, object.name
+                                    ));
+                                }
+                                CollisionShape::Full
+                            }
+                        };
+                        for col in min_col..=max_col {
+                            for row in min_row..=max_row {
+                                let cell = CollisionCell {
+                                    kind,
+                                    shape: shape.clone(),
+                                };
+                                level.collision.insert(to_point(col, row)?, cell);
+                            }
+                        }
+                    }
+                }
+                ImportedLayer::Objects(name, objects) => {
+                    for object in objects {
+                        if !matches!(object.shape, ObjectShape::Point) {
+                            let shape = match object.shape {
+                                ObjectShape::Rectangle => "rectangle",
+                                ObjectShape::Tile => "tile object",
+                                ObjectShape::Polygon(_) => "polygon",
+                                ObjectShape::Other(shape) => shape,
+                                ObjectShape::Point => unreachable!(),
+                            };
+                            report.push(format!(
+                                "The {} \"{}\" in \"{}\" was skipped, only points become entities",
+                                shape, object.name, name
+                            ));
+                            continue;
+                        }
+                        let (col, row) = to_cell(object.x, object.y);
+                        let point = to_point(col, row)?;
+                        if object.name.is_empty() {
+                            report.push(format!("An unnamed point in \"{}\" was skipped", name));
+                            continue;
+                        }
+                        match level.entities.entry(point) {
+                            Entry::Occupied(_) => report.push(format!(
+                                "Point \"{}\" was skipped, its cell already has an entity",
+                                object.name
+                            )),
+                            Entry::Vacant(entry) => {
+                                if labels.insert(object.name.clone()) {
+                                    entry.insert(object.name);
+                                } else {
+                                    report.push(format!(
+                                        "Point \"{}\" was skipped, entity labels must be unique",
+                                        object.name
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+                ImportedLayer::Unsupported(name, kind) => {
+                    report.push(format!("The {} \"{}\" was skipped", kind, name));
+                }
+            }
+        }
+        if flipped > 0 {
+            report.push(format!(
+                "{} flipped or rotated tiles were imported unflipped",
+                flipped
+            ));
+        }
+        let (image_path, width, height) = tileset.image.clone().unwrap();
+        level.spritesheet = Some(SpritesheetSource {
+            path: image_path,
+            width,
+            height,
+            hash: None,
+        });
+        Ok((level, report))
+    }
+
+    /// The collision kind named `class`, ignoring case, adding it when the level has none by that
+    /// name. Objects without a class are the first kind. None once a new kind would no longer fit
+    /// in a `u16`.
+    fn imported_collision_kind(&mut self, class: &str, report: &mut Vec<String>) -> Option<u16> {
+        if class.is_empty() {
+            return Some(0);
+        }
+        let existing = self
+            .collision_kinds
+            .iter()
+            .position(|kind| kind.name.eq_ignore_ascii_case(class));
+        let index = match existing {
+            Some(index) => index,
+            None if self.collision_kinds.len() > u16::MAX as usize => return None,
+            None => {
+                let color =
+                    IMPORTED_KIND_COLORS[self.collision_kinds.len() % IMPORTED_KIND_COLORS.len()];
+                self.collision_kinds.push(CollisionKind::new(class, color));
+                report.push(format!("Collision kind \"{}\" was added", class));
+                self.collision_kinds.len() - 1
+            }
+        };
+        u16::try_from(index).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{reinserted, sample_level, test_dir};
+
+    /// Everything but the int grid, the spritesheet's hash and the custom kind's color survives.
+    fn round_trip(extension: &str) {
+        let dir = test_dir(&format!("tiled-round-trip-{}", extension));
+        let level = sample_level(&dir);
+        let path = dir.join(format!("level.{}", extension));
+        let exported = level.export_tiled(&path).unwrap();
+        assert_eq!(exported.len(), 1, "only the int grid is left out");
+        let (read, imported) = Level::import_tiled(&path).unwrap();
+        // Tiled keeps no palette, so the custom kind comes back with a color of its own
+        assert_eq!(imported, ["Collision kind \"Spikes\" was added"]);
+        let mut spritesheet = level.spritesheet.clone().unwrap();
+        spritesheet.hash = None;
+        assert_eq!(read.spritesheet, Some(spritesheet));
+        assert_eq!(read.spritesheet_info, level.spritesheet_info);
+        assert_eq!(read.layers, level.layers);
+        assert_eq!(read.collision, level.collision);
+        let kind_names = |level: &Level| {
+            let kinds = level.collision_kinds.iter();
+            kinds.map(|kind| kind.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(kind_names(&read), kind_names(&level));
+        assert_eq!(read.entities, level.entities);
+    }
+
+    fn writes_are_deterministic(extension: &str) {
+        let dir = test_dir(&format!("tiled-deterministic-{}", extension));
+        let level = sample_level(&dir);
+        let path = dir.join(format!("level.{}", extension));
+        level.export_tiled(&path).unwrap();
+        let first = std::fs::read(&path).unwrap();
+        reinserted(&level).export_tiled(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), first);
+    }
+
+    #[test]
+    fn tmx_writes_are_deterministic() {
+        writes_are_deterministic("tmx");
+    }
+
+    #[test]
+    fn tmj_writes_are_deterministic() {
+        writes_are_deterministic("tmj");
+    }
+
+    #[test]
+    fn far_apart_cells_are_refused() {
+        let dir = test_dir("tiled-far-apart");
+        let mut level = Level::default();
+        let point = |x, y| HashableVec2 { x, y };
+        level.entities = HashMap::from([
+            (point(0, 0), "a".to_string()),
+            (point(1 << 40, 1 << 40), "b".to_string()),
+        ]);
+        assert!(matches!(
+            level.export_tiled(&dir.join("level.tmx")),
+            Err(LevelIoError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_tmj_gids_are_reported() {
+        let dir = test_dir("tiled-invalid-gids");
+        let path = dir.join("level.tmj");
+        sample_level(&dir).export_tiled(&path).unwrap();
+        let mut map: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let layer = &mut map["layers"][0];
+        let name = layer["name"].as_str().unwrap().to_string();
+        let data = layer["data"].as_array_mut().unwrap();
+        data[..3].clone_from_slice(&[json!(-1), json!(1.5), json!(1u64 << 40)]);
+        std::fs::write(&path, map.to_string()).unwrap();
+        let (_, imported) = Level::import_tiled(&path).unwrap();
+        let skipped = format!("3 tiles in \"{}\" with invalid gids were skipped", name);
+        assert!(imported.contains(&skipped), "{:?}", imported);
+    }
+
+    #[test]
+    fn collision_kinds_stop_at_the_largest_index() {
+        let mut level = Level::default();
+        let mut report = Vec::new();
+        while level.collision_kinds.len() <= u16::MAX as usize {
+            let name = format!("Kind {}", level.collision_kinds.len());
+            level
+                .collision_kinds
+                .push(CollisionKind::new(name, [0, 0, 0]));
+        }
+        assert_eq!(level.imported_collision_kind("New", &mut report), None);
+        assert_eq!(
+            level.imported_collision_kind("kind 65535", &mut report),
+            Some(u16::MAX)
+        );
+        assert_eq!(level.imported_collision_kind("", &mut report), Some(0));
+        assert!(report.is_empty());
+        assert_eq!(level.collision_kinds.len(), u16::MAX as usize + 1);
+    }
+
+    #[test]
+    fn tmx_round_trip() {
+        round_trip("tmx");
+    }
+
+    #[test]
+    fn tmj_round_trip() {
+        round_trip("tmj");
+    }
+}
@@ -0,0 +1,264 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct HashableVec2 {
+    pub x: i64,
+    pub y: i64,
+}
+
+/// How the spritesheet is cut into tiles. `num_rows` counts tiles along the x axis and
+/// `num_cols` along the y axis.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpritesheetInfo {
+    pub sprite_size: u16,
+    pub num_rows: u8,
+    pub num_cols: u8,
+}
+
+impl Default for SpritesheetInfo {
+    fn default() -> Self {
+        Self {
+            sprite_size: 32,
+            num_rows: 10,
+            num_cols: 10,
+        }
+    }
+}
+
+/// The smallest rectangle of cells, inclusive, that holds everything placed in a level.
+#[derive(Copy, Clone, Debug)]
+pub struct LevelBounds {
+    pub min_x: i64,
+    pub max_x: i64,
+    pub min_y: i64,
+    pub max_y: i64,
+}
+
+impl LevelBounds {
+    pub fn width(&self) -> i64 {
+        self.max_x - self.min_x + 1
+    }
+    pub fn height(&self) -> i64 {
+        self.max_y - self.min_y + 1
+    }
+    /// How many cells the bounds hold, or `None` if that doesn't fit a u64.
+    pub fn cell_count(&self) -> Option<u64> {
+        let width = u64::try_from(self.max_x as i128 - self.min_x as i128 + 1).ok()?;
+        let height = u64::try_from(self.max_y as i128 - self.min_y as i128 + 1).ok()?;
+        width.checked_mul(height)
+    }
+    /// Column and row of `point` inside the bounds, with row 0 at the top as image and map
+    /// formats expect.
+    pub fn to_cell(self, point: &HashableVec2) -> (i64, i64) {
+        (point.x - self.min_x, self.max_y - point.y)
+    }
+}
+
+/// A tile's column and row in the spritesheet grid. Placed tiles are stored this way rather than
+/// as texture coordinates so a level doesn't depend on a spritesheet being loaded.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct TileIndex {
+    pub col: u16,
+    pub row: u16,
+}
+
+/// Where the level's spritesheet is, recorded in saved levels so they can find it again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpritesheetSource {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// `hash_bytes` of the image file. Formats that don't record one can't tell when the sheet
+    /// has changed.
+    pub hash: Option<u64>,
+}
+
+/// A kind of collision, e.g. solid ground or a ladder. Collision cells refer to kinds by their
+/// index in `Level::collision_kinds`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollisionKind {
+    pub name: String,
+    /// sRGB.
+    pub color: [u8; 3],
+}
+
+impl CollisionKind {
+    pub fn new(name: impl Into<String>, color: [u8; 3]) -> Self {
+        Self {
+            name: name.into(),
+            color,
+        }
+    }
+}
+
+/// The corner of its cell a slope's solid part is in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Corner {
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight,
+}
+
+impl Corner {
+    pub const ALL: [Corner; 4] = [
+        Corner::BottomLeft,
+        Corner::BottomRight,
+        Corner::TopLeft,
+        Corner::TopRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Corner::BottomLeft => "bottom left",
+            Corner::BottomRight => "bottom right",
+            Corner::TopLeft => "top left",
+            Corner::TopRight => "top right",
+        }
+    }
+}
+
+/// The part of its cell a collision cell covers. Outlines are in cell space, from (0, 0) at the
+/// cell's bottom left corner to (1, 1) at its top right, with y pointing up as on the plot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CollisionShape {
+    #[default]
+    Full,
+    HalfTop,
+    HalfBottom,
+    HalfLeft,
+    HalfRight,
+    /// The triangle below the cell's diagonal, with its right angle in the corner.
+    Slope45(Corner),
+    /// A 22.5° slope rises half a cell over a cell's width, so it takes two cells. This is the
+    /// lower one, a triangle half a cell high at the corner.
+    Slope22Low(Corner),
+    /// The higher cell of a 22.5° slope, half a cell high away from the corner and full height
+    /// at it.
+    Slope22High(Corner),
+    /// Any other outline, as its corners in order.
+    Polygon(Vec<[f32; 2]>),
+}
+
+impl CollisionShape {
+    /// Every shape but `Polygon`, in the order they're offered to paint with.
+    pub const PRESETS: [CollisionShape; 17] = [
+        CollisionShape::Full,
+        CollisionShape::HalfTop,
+        CollisionShape::HalfBottom,
+        CollisionShape::HalfLeft,
+        CollisionShape::HalfRight,
+        CollisionShape::Slope45(Corner::BottomLeft),
+        CollisionShape::Slope45(Corner::BottomRight),
+        CollisionShape::Slope45(Corner::TopLeft),
+        CollisionShape::Slope45(Corner::TopRight),
+        CollisionShape::Slope22Low(Corner::BottomLeft),
+        CollisionShape::Slope22Low(Corner::BottomRight),
+        CollisionShape::Slope22Low(Corner::TopLeft),
+        CollisionShape::Slope22Low(Corner::TopRight),
+        CollisionShape::Slope22High(Corner::BottomLeft),
+        CollisionShape::Slope22High(Corner::BottomRight),
+        CollisionShape::Slope22High(Corner::TopLeft),
+        CollisionShape::Slope22High(Corner::TopRight),
+    ];
+
+    /// Formats only record the shapes of cells that aren't full.
+    pub fn is_full(&self) -> bool {
+        *self == CollisionShape::Full
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            CollisionShape::Full => "Full".to_string(),
+            CollisionShape::HalfTop => "Top half".to_string(),
+            CollisionShape::HalfBottom => "Bottom half".to_string(),
+            CollisionShape::HalfLeft => "Left half".to_string(),
+            CollisionShape::HalfRight => "Right half".to_string(),
+            CollisionShape::Slope45(corner) => format!("Slope 45° ({})", corner.name()),
+            CollisionShape::Slope22Low(corner) => format!("Slope 22.5° low ({})", corner.name()),
+            CollisionShape::Slope22High(corner) => {
+                format!("Slope 22.5° high ({})", corner.name())
+            }
+            CollisionShape::Polygon(_) => "Polygon".to_string(),
+        }
+    }
+
+    /// The shape's corners in cell space. Slopes are worked out for the bottom left corner and
+    /// then mirrored into theirs.
+    pub fn outline(&self) -> Vec<[f32; 2]> {
+        let (corner, outline) = match self {
+            CollisionShape::Full => return vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            CollisionShape::HalfTop => return vec![[0.0, 0.5], [1.0, 0.5], [1.0, 1.0], [0.0, 1.0]],
+            CollisionShape::HalfBottom => {
+                return vec![[0.0, 0.0], [1.0, 0.0], [1.0, 0.5], [0.0, 0.5]]
+            }
+            CollisionShape::HalfLeft => {
+                return vec![[0.0, 0.0], [0.5, 0.0], [0.5, 1.0], [0.0, 1.0]]
+            }
+            CollisionShape::HalfRight => {
+                return vec![[0.5, 0.0], [1.0, 0.0], [1.0, 1.0], [0.5, 1.0]]
+            }
+            CollisionShape::Slope45(corner) => (corner, vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]),
+            CollisionShape::Slope22Low(corner) => {
+                (corner, vec![[0.0, 0.0], [1.0, 0.0], [0.0, 0.5]])
+            }
+            CollisionShape::Slope22High(corner) => {
+                (corner, vec![[0.0, 0.0], [1.0, 0.0], [1.0, 0.5], [0.0, 1.0]])
+            }
+            CollisionShape::Polygon(outline) => return outline.clone(),
+        };
+        let flip_x = matches!(corner, Corner::BottomRight | Corner::TopRight);
+        let flip_y = matches!(corner, Corner::TopLeft | Corner::TopRight);
+        outline
+            .into_iter()
+            .map(|[x, y]| {
+                [
+                    if flip_x { 1.0 - x } else { x },
+                    if flip_y { 1.0 - y } else { y },
+                ]
+            })
+            .collect()
+    }
+}
+
+/// What fills a cell of the collision layer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollisionCell {
+    /// Index into `Level::collision_kinds`.
+    pub kind: u16,
+    pub shape: CollisionShape,
+}
+
+impl CollisionCell {
+    /// A full cell of `kind`, which is all collision was before cells had shapes.
+    pub fn full(kind: u16) -> Self {
+        Self {
+            kind,
+            shape: CollisionShape::Full,
+        }
+    }
+}
+
+/// One of the values an `IntGridLayer`'s cells can hold, e.g. room 3 or a spawn zone.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntGridValue {
+    /// At least 1, 0 being an empty cell in the formats that store grids.
+    pub value: u32,
+    pub name: String,
+    /// sRGB.
+    pub color: [u8; 3],
+}
+
+/// The palette new levels start with. Formats from before collision had kinds only stored the
+/// first, solid collision.
+pub fn default_collision_kinds() -> Vec<CollisionKind> {
+    vec![
+        CollisionKind::new("Solid", [255, 0, 0]),
+        CollisionKind::new("One-way Platform", [255, 165, 0]),
+        CollisionKind::new("Ladder", [160, 82, 45]),
+        CollisionKind::new("Water", [30, 144, 255]),
+        CollisionKind::new("Hazard", [255, 0, 255]),
+    ]
+}
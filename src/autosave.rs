@@ -0,0 +1,188 @@
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use rs_level::Level;
+
+use crate::file::{data_dir, hash_bytes, replace_file};
+use crate::MyApp;
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// Each editor copies its level to its own file in the data directory every
+/// `AUTOSAVE_INTERVAL`, named after its process ID, and removes it when it exits cleanly. While
+/// it runs it also holds a lock on a file of the same name with `LOCK_EXTENSION`, which the OS
+/// lets go of however the editor ends, so recovery files whose lock is free on startup were left
+/// by sessions that crashed or were killed.
+const RECOVERY_PREFIX: &str = "recovery";
+const RECOVERY_EXTENSION: &str = "lvl";
+const LOCK_EXTENSION: &str = "lock";
+
+/// Whether the session that owns the recovery file at `path` is still running. Files from
+/// versions that didn't lock have no lock file and are never live.
+fn is_live(path: &Path) -> bool {
+    match File::open(path.with_extension(LOCK_EXTENSION)) {
+        Ok(lock) => matches!(lock.try_lock(), Err(TryLockError::WouldBlock)),
+        Err(_) => false,
+    }
+}
+
+/// A file name no recovery file or lock has yet, so a crashed session whose process ID was
+/// reused is never overwritten.
+fn own_recovery_path(dir: &Path) -> PathBuf {
+    let id = std::process::id();
+    (1..)
+        .map(|n| match n {
+            1 => format!("{}-{}.{}", RECOVERY_PREFIX, id, RECOVERY_EXTENSION),
+            n => format!("{}-{}-{}.{}", RECOVERY_PREFIX, id, n, RECOVERY_EXTENSION),
+        })
+        .map(|name| dir.join(name))
+        .find(|path| !path.exists() && !path.with_extension(LOCK_EXTENSION).exists())
+        .expect("some numbered file name is free")
+}
+
+/// Every recovery file in `dir` whose session is gone, including the shared `recovery.lvl` older
+/// versions wrote, newest first.
+fn find_recovery_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut found: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_stem().and_then(|stem| stem.to_str());
+            name.is_some_and(|name| name.starts_with(RECOVERY_PREFIX))
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == RECOVERY_EXTENSION)
+                && !is_live(path)
+        })
+        .map(|path| {
+            let modified = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, path)
+        })
+        .collect();
+    found.sort_by(|a, b| b.cmp(a));
+    found.into_iter().map(|(_, path)| path).collect()
+}
+
+/// "saved 5 minutes ago", for telling recovery files apart.
+pub(crate) fn recovery_file_age(path: &Path) -> String {
+    let elapsed = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok());
+    let minutes = match elapsed {
+        Some(elapsed) => elapsed.as_secs() / 60,
+        None => return "saved at an unknown time".to_string(),
+    };
+    match minutes {
+        0 => "saved just now".to_string(),
+        1 => "saved a minute ago".to_string(),
+        minutes if minutes < 120 => format!("saved {} minutes ago", minutes),
+        minutes if minutes < 48 * 60 => format!("saved {} hours ago", minutes / 60),
+        minutes => format!("saved {} days ago", minutes / (24 * 60)),
+    }
+}
+
+impl MyApp {
+    /// Turns autosave on, offering to restore any recovery files earlier sessions left behind.
+    pub(crate) fn enable_autosave(&mut self) {
+        let dir = match data_dir() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let path = own_recovery_path(&dir);
+        // without the lock other editors would offer this session's file, so autosave stays off
+        let lock_path = path.with_extension(LOCK_EXTENSION);
+        let lock = match File::create(&lock_path) {
+            Ok(lock) if lock.try_lock().is_ok() => lock,
+            _ => return,
+        };
+        self.recovery_lock = Some((lock_path, lock));
+        self.recovery_files = find_recovery_files(&dir);
+        // nothing worth recovering until the empty level changes
+        self.last_autosave_hash = Some(hash_bytes(&self.level.write(&path)));
+        self.recovery_path = Some(path);
+    }
+
+    /// Writes the level to this editor's recovery file if it changed since the last autosave and
+    /// `AUTOSAVE_INTERVAL` has passed.
+    pub(crate) fn autosave(&mut self) {
+        if self.last_autosave.elapsed() < AUTOSAVE_INTERVAL {
+            return;
+        }
+        let path = match &self.recovery_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        self.last_autosave = Instant::now();
+        let buffer = self.level.write(&path);
+        let hash = hash_bytes(&buffer);
+        if self.last_autosave_hash == Some(hash) {
+            return;
+        }
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| replace_file(&path, &buffer, 0));
+        match result {
+            Ok(()) => self.last_autosave_hash = Some(hash),
+            Err(why) => {
+                // one popup is enough, not one a minute
+                self.recovery_path = None;
+                self.error_message = Some(format!(
+                    "Autosave is off, couldn't write {}: {}",
+                    path.display(),
+                    why
+                ));
+            }
+        }
+    }
+
+    /// Opens a recovery file another session left behind in place of the empty level. Once it
+    /// has opened the file becomes this editor's own, so it is kept until this session exits
+    /// cleanly. A file that doesn't open is left where it is.
+    pub(crate) fn restore_recovery_file(&mut self, found: PathBuf) {
+        self.recovery_files.clear();
+        let level = match Level::open(&found, self.level.with_same_spritesheet()) {
+            Ok(level) => level,
+            Err(why) => {
+                self.report_io_error("restore", &found, Err(why));
+                return;
+            }
+        };
+        self.load_level(level);
+        // the restored level still has to be saved somewhere
+        self.level_path = None;
+        self.mark_modified();
+        if let Some(own) = &self.recovery_path {
+            if std::fs::rename(&found, own).is_ok() {
+                let _ = std::fs::remove_file(found.with_extension(LOCK_EXTENSION));
+            }
+        }
+    }
+
+    /// Deletes a recovery file another session left behind, when the user doesn't want it.
+    pub(crate) fn discard_recovery_file(&mut self, found: &Path) {
+        let _ = std::fs::remove_file(found);
+        let _ = std::fs::remove_file(found.with_extension(LOCK_EXTENSION));
+        self.recovery_files.retain(|path| path != found);
+    }
+
+    /// Deletes this editor's own recovery file and lock on a clean exit, leaving other sessions'
+    /// alone.
+    pub(crate) fn remove_recovery_file(&mut self) {
+        if let Some(path) = &self.recovery_path {
+            let _ = std::fs::remove_file(path);
+        }
+        // unlocked first, Windows won't remove a file that is open
+        if let Some((lock_path, lock)) = self.recovery_lock.take() {
+            drop(lock);
+            let _ = std::fs::remove_file(lock_path);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use eframe::egui;
+
+use crate::types::*;
+use crate::MyApp;
+
+/// Reads corners typed as `x,y` pairs separated by spaces, e.g. `0,0 1,0 0,1`, in cell space.
+/// `None` unless there are at least three and each lies inside the cell.
+fn parse_polygon(text: &str) -> Option<Vec<[f32; 2]>> {
+    let corners = text
+        .split_whitespace()
+        .map(|corner| {
+            let (x, y) = corner.split_once(',')?;
+            let corner = [x.trim().parse().ok()?, y.trim().parse().ok()?];
+            corner
+                .iter()
+                .all(|coordinate| (0.0..=1.0).contains(coordinate))
+                .then_some(corner)
+        })
+        .collect::<Option<Vec<[f32; 2]>>>()?;
+    (corners.len() >= 3).then_some(corners)
+}
+
+/// The text `parse_polygon` reads back as `corners`.
+fn format_polygon(corners: &[[f32; 2]]) -> String {
+    let corners: Vec<String> = corners
+        .iter()
+        .map(|[x, y]| format!("{},{}", x, y))
+        .collect();
+    corners.join(" ")
+}
+
+/// A selectable square with the outline of `shape` drawn in it the way the plot draws it.
+fn shape_button(
+    ui: &mut egui::Ui,
+    shape: &CollisionShape,
+    selected: bool,
+    color: egui::Color32,
+) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(22.0, 22.0), egui::Sense::click());
+    let visuals = ui.style().interact_selectable(&response, selected);
+    ui.painter()
+        .rect(rect, visuals.rounding, visuals.bg_fill, visuals.bg_stroke);
+    let cell = rect.shrink(4.0);
+    let corners = shape
+        .outline()
+        .into_iter()
+        .map(|[x, y]| {
+            egui::pos2(
+                cell.left() + x * cell.width(),
+                cell.bottom() - y * cell.height(),
+            )
+        })
+        .collect();
+    ui.painter().add(egui::Shape::closed_line(
+        corners,
+        egui::Stroke::new(1.5, color),
+    ));
+    response
+}
+
+impl MyApp {
+    /// Paints `shape` from now on. Polygons also fill in the custom polygon's corners, so a
+    /// picked one can be tweaked.
+    pub(crate) fn select_collision_shape(&mut self, shape: CollisionShape) {
+        if let CollisionShape::Polygon(corners) = &shape {
+            self.custom_collision_polygon = format_polygon(corners);
+        }
+        self.selected_collision_shape = shape;
+        self.current_mode = Mode::Collision;
+    }
+
+    /// The shapes `Mode::Collision` paints, drawn in the selected kind's color, and a custom
+    /// polygon typed as its corners.
+    pub(crate) fn collision_shapes_section(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Collision Shapes").show(ui, |ui| {
+            let color = match self
+                .level
+                .collision_kinds
+                .get(self.selected_collision_kind as usize)
+            {
+                Some(kind) => egui::Color32::from_rgb(kind.color[0], kind.color[1], kind.color[2]),
+                None => ui.visuals().text_color(),
+            };
+            let painting = self.current_mode == Mode::Collision;
+            let mut picked = None;
+            ui.horizontal_wrapped(|ui| {
+                for shape in &CollisionShape::PRESETS {
+                    let selected = painting && self.selected_collision_shape == *shape;
+                    if shape_button(ui, shape, selected, color)
+                        .on_hover_text(shape.name())
+                        .clicked()
+                    {
+                        picked = Some(shape.clone());
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                let polygon = parse_polygon(&self.custom_collision_polygon);
+                let selected =
+                    painting && matches!(self.selected_collision_shape, CollisionShape::Polygon(_));
+                let radio = ui
+                    .add_enabled(
+                        polygon.is_some(),
+                        egui::RadioButton::new(selected, "Polygon"),
+                    )
+                    .on_hover_text("Paint with the polygon whose corners are typed here")
+                    .on_disabled_hover_text(
+                        "Type at least three x,y corners between 0,0 (bottom left) and 1,1",
+                    );
+                let edit = ui.add(
+                    egui::TextEdit::singleline(&mut self.custom_collision_polygon)
+                        .hint_text("0,0 1,0 0,1")
+                        .desired_width(100.0),
+                );
+                if radio.clicked() || edit.changed() {
+                    if let Some(corners) = parse_polygon(&self.custom_collision_polygon) {
+                        self.selected_collision_shape = CollisionShape::Polygon(corners);
+                        self.current_mode = Mode::Collision;
+                    }
+                }
+            });
+            if let Some(shape) = picked {
+                self.select_collision_shape(shape);
+            }
+        });
+    }
+}
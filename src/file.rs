@@ -1,9 +1,21 @@
-use std::fs::File;
-use std::io::prelude::*;
 use std::path::PathBuf;
 
-use crate::{HashableVec2, MyApp};
-use eframe::egui::{Pos2, Rect};
+pub use rs_level::{hash_bytes, replace_file, LevelIoError};
+use rs_level::{Level, TEXT_LEVEL_EXTENSION};
+
+use crate::MyApp;
+
+pub const IMAGE_EXTENSIONS: &[&str] = &["webp", "png", "bmp", "jpg", "jpeg"];
+/// Every format `MyApp::open` and `MyApp::save` understand, picked by extension.
+pub const LEVEL_FILTER: (&str, &[&str]) = ("Level", &["lvl", TEXT_LEVEL_EXTENSION]);
+/// Save dialogs offer each level format on its own, binary first.
+pub const LEVEL_SAVE_FILTERS: &[(&str, &[&str])] =
+    &[("Level", &["lvl"]), ("Text Level", &[TEXT_LEVEL_EXTENSION])];
+
+/// Where the editor keeps its own files between sessions.
+pub fn data_dir() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("rs-level-editor"))
+}
 
 pub fn pick_file_to(var: &mut Option<PathBuf>, filter: (&str, &[&str])) {
     if let Some(path) = rfd::FileDialog::new()
@@ -14,176 +26,99 @@ pub fn pick_file_to(var: &mut Option<PathBuf>, filter: (&str, &[&str])) {
     }
 }
 
-pub fn save_file_to(var: &mut Option<PathBuf>, filter: (&str, &[&str])) {
-    if let Some(path) = rfd::FileDialog::new()
-        .add_filter(filter.0, filter.1)
-        .save_file()
-    {
+pub fn save_file_to(var: &mut Option<PathBuf>, filters: &[(&str, &[&str])]) {
+    let dialog = filters
+        .iter()
+        .fold(rfd::FileDialog::new(), |dialog, (name, extensions)| {
+            dialog.add_filter(name, extensions)
+        });
+    if let Some(path) = dialog.save_file() {
         *var = Some(path);
     }
 }
 
 impl MyApp {
-    pub(crate) fn save(&mut self, path: PathBuf) {
-        let display = path.display();
-        let mut file = match File::create(&path) {
-            Err(why) => {
-                println!("Couldn't create {}: {}", display, why);
-                return;
-            }
-            Ok(f) => f,
+    /// Queues the level's spritesheet to be loaded. If it has moved, `missing_spritesheet` is set
+    /// so the editor can ask the user to find it.
+    fn locate_spritesheet(&mut self) {
+        let source = match &self.level.spritesheet {
+            Some(source) => source,
+            None => return,
         };
-        if self.background_plotted_tiles.is_empty() || self.spritesheet_handle.is_none() {
-            return;
-        }
-        let handle = self.spritesheet_handle.as_ref().unwrap();
-        let handle_size = handle.size_vec2();
-        let len_bg = (self.background_plotted_tiles.len() * 20) as u64;
-        let len_fg = (self.foreground_plotted_tiles.len() * 20) as u64;
-        let len_collision = (self.collision_tiles.len() * 16) as u64;
-        let len_entity = (self.entity_tiles.len() * 64) as u64; // we don't know label length
-        let mut buffer: Vec<u8> =
-            Vec::with_capacity((len_bg + len_fg + len_collision + len_entity) as usize);
-        buffer.extend_from_slice(&len_bg.to_le_bytes()); // 36
-        buffer.extend_from_slice(&len_fg.to_le_bytes()); // 56
-        buffer.extend_from_slice(&len_collision.to_le_bytes()); // 72
-        for (point, uv) in self.background_plotted_tiles.iter() {
-            let x = point.x.to_le_bytes();
-            let y = point.y.to_le_bytes();
-            let row = ((uv.min.x * handle_size.x) as i16).to_le_bytes();
-            let col = ((uv.min.y * handle_size.y) as i16).to_le_bytes();
-            buffer.extend_from_slice(&x);
-            buffer.extend_from_slice(&y);
-            buffer.extend_from_slice(&row);
-            buffer.extend_from_slice(&col);
-        }
-        for (point, uv) in self.foreground_plotted_tiles.iter() {
-            let x = point.x.to_le_bytes();
-            let y = point.y.to_le_bytes();
-            let row = ((uv.min.x * handle_size.x) as i16).to_le_bytes();
-            let col = ((uv.min.y * handle_size.y) as i16).to_le_bytes();
-            buffer.extend_from_slice(&x);
-            buffer.extend_from_slice(&y);
-            buffer.extend_from_slice(&row);
-            buffer.extend_from_slice(&col);
-        }
-        for point in self.collision_tiles.iter() {
-            let x = point.x.to_le_bytes();
-            let y = point.y.to_le_bytes();
-            buffer.extend_from_slice(&x);
-            buffer.extend_from_slice(&y);
-        }
-        for (point, label) in self.entity_tiles.iter() {
-            let x = point.x.to_le_bytes();
-            let y = point.y.to_le_bytes();
-            let label_len = (label.len() as u64).to_le_bytes();
-            let label = label.as_bytes();
-            buffer.extend_from_slice(&x);
-            buffer.extend_from_slice(&y);
-            buffer.extend_from_slice(&label_len);
-            buffer.extend_from_slice(label);
+        match std::fs::read(&source.path) {
+            Ok(sheet_bytes) => {
+                if source
+                    .hash
+                    .is_some_and(|hash| hash != hash_bytes(&sheet_bytes))
+                {
+                    self.error_message = Some(format!(
+                        "Spritesheet {} has changed since this level was saved",
+                        source.path.display()
+                    ));
+                }
+                self.pending_spritesheet_path = Some(source.path.clone());
+            }
+            Err(_) => {
+                self.missing_spritesheet = Some(source.path.clone());
+            }
         }
-        file.write_all(&buffer).unwrap();
-        file.flush().unwrap();
     }
 
-    pub(crate) fn open(&mut self, path: PathBuf) {
-        if self.spritesheet_handle.is_none() {
-            return;
-        }
-        self.clear();
-        let handle = self.spritesheet_handle.as_ref().unwrap();
-        let handle_size = handle.size_vec2();
+    pub(crate) fn save(&mut self, path: PathBuf) -> Result<(), LevelIoError> {
+        self.level.save(&path)?;
+        self.level_path = Some(path);
+        self.mark_saved();
+        Ok(())
+    }
 
-        let display = path.display();
-        let mut file = match File::open(&path) {
-            Err(why) => {
-                println!("Couldn't create {}: {}", display, why);
-                return;
-            }
-            Ok(f) => f,
-        };
-        let mut buf: Vec<u8> = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
-        let mut index = 0;
-        let len_bg = u64::from_le_bytes(buf[index..index + 8].try_into().unwrap());
-        index += 8;
-        let len_fg = u64::from_le_bytes(buf[index..index + 8].try_into().unwrap());
-        index += 8;
-        let len_collision = u64::from_le_bytes(buf[index..index + 8].try_into().unwrap());
-        index += 8;
-        let bg_fg_stride_len = (8 + 8 + 2 + 2) as usize;
-        let background_bytes = &buf[index..index + len_bg as usize];
-        for chunk in background_bytes.chunks_exact(bg_fg_stride_len) {
-            let x = i64::from_le_bytes(chunk[0..8].try_into().unwrap());
-            let y = i64::from_le_bytes(chunk[8..16].try_into().unwrap());
-            let row = i16::from_le_bytes(chunk[16..18].try_into().unwrap());
-            let col = i16::from_le_bytes(chunk[18..20].try_into().unwrap());
-            let uv_min_x = row as f32 / handle_size.x;
-            let uv_min_y = col as f32 / handle_size.y;
-            let uv_max_x = (row as f32 + handle_size.x / self.spritesheet_info.num_rows as f32)
-                / handle_size.x;
-            let uv_max_y = (col as f32 + handle_size.y / self.spritesheet_info.num_cols as f32)
-                / handle_size.y;
-            let uv = Rect {
-                min: Pos2 {
-                    x: uv_min_x,
-                    y: uv_min_y,
-                },
-                max: Pos2 {
-                    x: uv_max_x,
-                    y: uv_max_y,
-                },
-            };
-            self.background_plotted_tiles
-                .insert(HashableVec2 { x, y }, uv);
-        }
-        index += len_bg as usize;
-        let foreground_bytes = &buf[index..index + len_fg as usize];
-        for chunk in foreground_bytes.chunks_exact(bg_fg_stride_len) {
-            let x = i64::from_le_bytes(chunk[0..8].try_into().unwrap());
-            let y = i64::from_le_bytes(chunk[8..16].try_into().unwrap());
-            let row = i16::from_le_bytes(chunk[16..18].try_into().unwrap());
-            let col = i16::from_le_bytes(chunk[18..20].try_into().unwrap());
-            let uv_min_x = row as f32 / handle_size.x;
-            let uv_min_y = col as f32 / handle_size.y;
-            let uv_max_x = (row as f32 + handle_size.x / self.spritesheet_info.num_rows as f32)
-                / handle_size.x;
-            let uv_max_y = (col as f32 + handle_size.y / self.spritesheet_info.num_cols as f32)
-                / handle_size.y;
-            let uv = Rect {
-                min: Pos2 {
-                    x: uv_min_x,
-                    y: uv_min_y,
-                },
-                max: Pos2 {
-                    x: uv_max_x,
-                    y: uv_max_y,
-                },
-            };
-            self.foreground_plotted_tiles
-                .insert(HashableVec2 { x, y }, uv);
-        }
-        index += len_fg as usize;
-        let collision_stride_len = (8 + 8) as usize;
-        let collision_bytes = &buf[index..index + len_collision as usize];
-        for chunk in collision_bytes.chunks_exact(collision_stride_len) {
-            let x = i64::from_le_bytes(chunk[0..8].try_into().unwrap());
-            let y = i64::from_le_bytes(chunk[8..16].try_into().unwrap());
-            self.collision_tiles.insert(HashableVec2 { x, y });
-        }
-        index += len_collision as usize;
-        let len = buf.len();
-        while index < len {
-            let x = i64::from_le_bytes(buf[index..index + 8].try_into().unwrap());
-            index += 8;
-            let y = i64::from_le_bytes(buf[index..index + 8].try_into().unwrap());
-            index += 8;
-            let label_len = u64::from_le_bytes(buf[index..index + 8].try_into().unwrap());
-            index += 8;
-            let label = String::from_utf8(buf[index..index + label_len as usize].into()).unwrap();
-            self.entity_tiles.insert(HashableVec2 { x, y }, label);
-            index += label_len as usize;
+    pub(crate) fn open(&mut self, path: PathBuf) -> Result<(), LevelIoError> {
+        let level = Level::open(&path, self.level.with_same_spritesheet())?;
+        self.load_level(level);
+        self.level_path = Some(path);
+        self.mark_saved();
+        Ok(())
+    }
+
+    pub(crate) fn import_json(&mut self, path: PathBuf) -> Result<(), LevelIoError> {
+        let level = Level::import_json(&path, self.level.with_same_spritesheet())?;
+        self.load_level(level);
+        Ok(())
+    }
+
+    /// Returns a line for everything in the map that couldn't be imported.
+    pub(crate) fn import_tiled(&mut self, path: PathBuf) -> Result<Vec<String>, LevelIoError> {
+        let (level, report) = Level::import_tiled(&path)?;
+        self.load_level(level);
+        Ok(report)
+    }
+
+    pub(crate) fn import_csv(&mut self, path: PathBuf) -> Result<(), LevelIoError> {
+        let level = Level::import_csv(&path, self.level.with_same_spritesheet())?;
+        self.load_level(level);
+        Ok(())
+    }
+
+    /// Replaces the open level, loading its spritesheet unless it is the one already open. The
+    /// new level counts as unsaved until `open` says otherwise.
+    pub(crate) fn load_level(&mut self, level: Level) {
+        let current_hash = self
+            .level
+            .spritesheet
+            .as_ref()
+            .and_then(|source| source.hash);
+        let new_hash = level.spritesheet.as_ref().and_then(|source| source.hash);
+        let already_loaded =
+            self.spritesheet_handle.is_some() && current_hash.is_some() && current_hash == new_hash;
+        self.clear();
+        self.entity_descriptions = level.entities.values().cloned().collect();
+        self.level = level;
+        self.reset_layer_views();
+        self.reset_int_grid_views();
+        self.selected_collision_kind = 0;
+        self.level_path = None;
+        self.mark_modified();
+        if !already_loaded {
+            self.locate_spritesheet();
         }
     }
 }
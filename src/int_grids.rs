@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+
+use crate::layers_panel::layer_view_controls;
+use crate::types::*;
+use crate::MyApp;
+
+/// A change picked in the int grids section, made once it is done being drawn.
+enum IntGridEdit {
+    Remove(usize),
+    RemoveValue(usize),
+}
+
+impl MyApp {
+    /// Called whenever `Level::int_grids` is replaced wholesale, e.g. by opening a level.
+    pub(crate) fn reset_int_grid_views(&mut self) {
+        self.int_grid_views = vec![LayerView::default(); self.level.int_grids.len()];
+        self.select_int_grid(0);
+        self.renaming_int_grid = None;
+    }
+
+    /// Paints on the int grid at `index` from now on, with its first value unless the selected
+    /// value is one of its own.
+    pub(crate) fn select_int_grid(&mut self, index: usize) {
+        self.current_int_grid = index;
+        if let Some(layer) = self.level.int_grids.get(index) {
+            let defined = self
+                .selected_int_grid_value
+                .is_some_and(|value| layer.value(value).is_some());
+            if !defined {
+                self.selected_int_grid_value = layer.values.first().map(|value| value.value);
+            }
+        }
+    }
+
+    /// Inserts `layer` at `index` and paints on it.
+    pub(crate) fn insert_int_grid(&mut self, index: usize, layer: IntGridLayer) {
+        self.level.int_grids.insert(index, layer);
+        self.int_grid_views.insert(index, LayerView::default());
+        self.select_int_grid(index);
+        self.renaming_int_grid = None;
+    }
+
+    /// Removes the int grid at `index`, painting on the one that took its place, or the one
+    /// before it when it was the last.
+    pub(crate) fn remove_int_grid(&mut self, index: usize) -> IntGridLayer {
+        let layer = self.level.int_grids.remove(index);
+        self.int_grid_views.remove(index);
+        if self.current_int_grid > index || self.current_int_grid >= self.level.int_grids.len() {
+            self.select_int_grid(self.current_int_grid.saturating_sub(1));
+        }
+        if self.level.int_grids.is_empty() && self.current_mode == Mode::IntGrid {
+            self.current_mode = Mode::Collision;
+        }
+        self.renaming_int_grid = None;
+        layer
+    }
+
+    /// Adds an int grid after the current one, with a first value to paint.
+    fn add_int_grid(&mut self) {
+        let index = match self.level.int_grids.is_empty() {
+            true => 0,
+            false => self.current_int_grid + 1,
+        };
+        let name = (self.level.int_grids.len() + 1..)
+            .map(|n| format!("Int Grid {}", n))
+            .find(|name| !self.level.int_grids.iter().any(|layer| &layer.name == name))
+            .unwrap();
+        let mut layer = IntGridLayer::new(name);
+        layer.values.push(IntGridValue {
+            value: 1,
+            name: "Value 1".to_string(),
+            color: palette_color(0),
+        });
+        self.insert_int_grid(index, layer);
+        self.current_mode = Mode::IntGrid;
+        self.push_action(Action::AddIntGrid(index));
+    }
+
+    /// The number after the current int grid's largest value, `None` once that is `u32::MAX`.
+    fn next_int_grid_value(&self) -> Option<u32> {
+        self.level.int_grids[self.current_int_grid]
+            .values
+            .iter()
+            .map(|value| value.value)
+            .max()
+            .unwrap_or(0)
+            .checked_add(1)
+    }
+
+    /// Adds `value`, from `next_int_grid_value`, to the current int grid's values and paints
+    /// with it.
+    fn add_int_grid_value(&mut self, value: u32) {
+        let layer = self.current_int_grid;
+        let values = &mut self.level.int_grids[layer].values;
+        let index = values.len();
+        values.push(IntGridValue {
+            value,
+            name: format!("Value {}", value),
+            color: palette_color(index),
+        });
+        self.selected_int_grid_value = Some(value);
+        self.current_mode = Mode::IntGrid;
+        self.push_action(Action::AddIntGridValue(layer, index));
+    }
+
+    /// Applies the name typed for `renaming_int_grid`, leaving the old one if the new one is
+    /// blank.
+    fn finish_renaming_int_grid(&mut self) {
+        if let Some((index, name)) = self.renaming_int_grid.take() {
+            let name = name.trim().to_string();
+            if !name.is_empty() && name != self.level.int_grids[index].name {
+                let old_name = std::mem::replace(&mut self.level.int_grids[index].name, name);
+                self.push_action(Action::RenameIntGrid(index, old_name));
+            }
+        }
+    }
+
+    /// The current int grid's name, edited like a layer's.
+    fn int_grid_name_row(&mut self, ui: &mut egui::Ui) {
+        let index = self.current_int_grid;
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            match &mut self.renaming_int_grid {
+                Some((renaming, name)) if *renaming == index => {
+                    let response = ui.text_edit_singleline(name);
+                    if response.lost_focus() {
+                        if ui.input().key_pressed(egui::Key::Escape) {
+                            self.renaming_int_grid = None;
+                        } else {
+                            self.finish_renaming_int_grid();
+                        }
+                    } else if !response.has_focus() {
+                        response.request_focus();
+                    }
+                }
+                _ => {
+                    let name = &self.level.int_grids[index].name;
+                    if ui
+                        .selectable_label(false, name)
+                        .on_hover_text("Double click to rename")
+                        .double_clicked()
+                    {
+                        self.renaming_int_grid = Some((index, name.clone()));
+                    }
+                }
+            }
+        });
+    }
+
+    /// The current int grid's name and values, which can be renamed and recolored, and removed
+    /// once no cell holds them. The numbers stay as they were added.
+    fn int_grid_values_table(&mut self, ui: &mut egui::Ui) -> Option<IntGridEdit> {
+        let mut edit = None;
+        let mut modified = false;
+        self.int_grid_name_row(ui);
+        let layer = &mut self.level.int_grids[self.current_int_grid];
+        let used: HashSet<u32> = layer.cells.values().copied().collect();
+        for (index, value) in layer.values.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let selected = self.current_mode == Mode::IntGrid
+                    && self.selected_int_grid_value == Some(value.value);
+                if ui.radio(selected, "").on_hover_text("Paint with").clicked() {
+                    self.selected_int_grid_value = Some(value.value);
+                    self.current_mode = Mode::IntGrid;
+                }
+                ui.label(value.value.to_string());
+                modified |= ui.color_edit_button_srgb(&mut value.color).changed();
+                modified |= ui
+                    .add(egui::TextEdit::singleline(&mut value.name).desired_width(100.0))
+                    .changed();
+                if ui
+                    .add_enabled(!used.contains(&value.value), egui::Button::new("🗙").small())
+                    .on_hover_text("Remove")
+                    .on_disabled_hover_text("Only values no cell holds can be removed")
+                    .clicked()
+                {
+                    edit = Some(IntGridEdit::RemoveValue(index));
+                }
+            });
+        }
+        if modified {
+            self.mark_modified();
+        }
+        let next_value = self.next_int_grid_value();
+        if ui
+            .add_enabled(next_value.is_some(), egui::Button::new("Add Value"))
+            .on_disabled_hover_text(format!("The largest value is already {}", u32::MAX))
+            .clicked()
+        {
+            if let Some(value) = next_value {
+                self.add_int_grid_value(value);
+            }
+        }
+        edit
+    }
+
+    /// Layers of integers for annotating the level, e.g. with room ids, painted in
+    /// `Mode::IntGrid` and shown as colored cells.
+    pub(crate) fn int_grids_section(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Int Grids").show(ui, |ui| {
+            if ui.button("Add Int Grid").clicked() {
+                self.add_int_grid();
+            }
+            let mut edit = None;
+            for index in 0..self.level.int_grids.len() {
+                ui.horizontal(|ui| {
+                    if ui.small_button("🗙").on_hover_text("Remove").clicked() {
+                        edit = Some(IntGridEdit::Remove(index));
+                    }
+                    layer_view_controls(ui, &mut self.int_grid_views[index]);
+                    let selected =
+                        self.current_mode == Mode::IntGrid && self.current_int_grid == index;
+                    if ui
+                        .selectable_label(selected, &self.level.int_grids[index].name)
+                        .clicked()
+                    {
+                        self.select_int_grid(index);
+                        self.current_mode = Mode::IntGrid;
+                    }
+                });
+            }
+            if self.current_int_grid < self.level.int_grids.len() {
+                ui.separator();
+                if let Some(table_edit) = self.int_grid_values_table(ui) {
+                    edit = Some(table_edit);
+                }
+            }
+            match edit {
+                Some(IntGridEdit::Remove(index)) => {
+                    let layer = self.remove_int_grid(index);
+                    self.push_action(Action::RemoveIntGrid(index, layer));
+                }
+                Some(IntGridEdit::RemoveValue(index)) => {
+                    let layer = self.current_int_grid;
+                    let value = self.level.int_grids[layer].values.remove(index);
+                    self.select_int_grid(layer);
+                    self.push_action(Action::RemoveIntGridValue(layer, index, value));
+                }
+                None => (),
+            }
+        });
+    }
+}
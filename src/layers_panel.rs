@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+
+use crate::types::*;
+use crate::MyApp;
+
+/// A change picked in the layers panel, made once the list is done being drawn.
+enum LayerEdit {
+    Remove(usize),
+    Move(usize, usize),
+}
+
+/// Lock and solo toggles and an opacity slider, for tile layers and the overlays alike.
+pub(crate) fn layer_view_controls(ui: &mut egui::Ui, view: &mut LayerView) {
+    ui.toggle_value(&mut view.locked, "🔒")
+        .on_hover_text("Lock, so clicking the plot can pick from the layer but not edit it");
+    ui.toggle_value(&mut view.solo, "S")
+        .on_hover_text("Solo, dimming the layers that aren't");
+    ui.add(egui::Slider::new(&mut view.opacity, 0.0..=1.0).show_value(false))
+        .on_hover_text("Opacity");
+}
+
+impl MyApp {
+    /// Called whenever `Level::layers` is replaced wholesale, e.g. by opening a level.
+    pub(crate) fn reset_layer_views(&mut self) {
+        self.layer_views = vec![LayerView::default(); self.level.layers.len()];
+        self.current_layer = 0;
+        self.renaming_layer = None;
+    }
+
+    /// Inserts `layer` at `index` and draws on it.
+    pub(crate) fn insert_layer(&mut self, index: usize, layer: TileLayer) {
+        self.level.layers.insert(index, layer);
+        self.layer_views.insert(index, LayerView::default());
+        self.current_layer = index;
+        self.renaming_layer = None;
+    }
+
+    /// Removes the layer at `index`, drawing on the one that took its place, or the one below it
+    /// when it was the top layer.
+    pub(crate) fn remove_layer(&mut self, index: usize) -> TileLayer {
+        let layer = self.level.layers.remove(index);
+        self.layer_views.remove(index);
+        if self.current_layer > index || self.current_layer >= self.level.layers.len() {
+            self.current_layer = self.current_layer.saturating_sub(1);
+        }
+        self.renaming_layer = None;
+        layer
+    }
+
+    /// Moves the layer at `from` to `to`, shifting the ones in between. The current layer stays
+    /// the same layer.
+    pub(crate) fn move_layer(&mut self, from: usize, to: usize) {
+        let layer = self.level.layers.remove(from);
+        self.level.layers.insert(to, layer);
+        let view = self.layer_views.remove(from);
+        self.layer_views.insert(to, view);
+        if self.current_layer == from {
+            self.current_layer = to;
+        } else if from < self.current_layer && self.current_layer <= to {
+            self.current_layer -= 1;
+        } else if to <= self.current_layer && self.current_layer < from {
+            self.current_layer += 1;
+        }
+        self.renaming_layer = None;
+    }
+
+    /// "Layer 3" for a level with two layers, or the next free number after it.
+    fn unused_layer_name(&self) -> String {
+        (self.level.layers.len() + 1..)
+            .map(|n| format!("Layer {}", n))
+            .find(|name| !self.level.layers.iter().any(|layer| &layer.name == name))
+            .unwrap()
+    }
+
+    /// Adds an empty layer above the current one.
+    fn add_layer(&mut self) {
+        let index = match self.level.layers.is_empty() {
+            true => 0,
+            false => self.current_layer + 1,
+        };
+        let layer = TileLayer::new(self.unused_layer_name());
+        self.insert_layer(index, layer);
+        self.current_mode = Mode::DrawTiles;
+        self.push_action(Action::AddLayer(index));
+    }
+
+    /// Inserts `kind` at `index` and paints with it, shifting the collision cells of the kinds
+    /// after it so they keep their kind.
+    pub(crate) fn insert_collision_kind(&mut self, index: usize, kind: CollisionKind) {
+        self.level.collision_kinds.insert(index, kind);
+        for cell in self.level.collision.values_mut() {
+            if cell.kind as usize >= index {
+                cell.kind += 1;
+            }
+        }
+        self.selected_collision_kind = index as u16;
+    }
+
+    /// Removes the collision kind at `index`, which no collision cell may use.
+    pub(crate) fn remove_collision_kind(&mut self, index: usize) -> CollisionKind {
+        let kind = self.level.collision_kinds.remove(index);
+        for cell in self.level.collision.values_mut() {
+            if cell.kind as usize > index {
+                cell.kind -= 1;
+            }
+        }
+        let selected = self.selected_collision_kind as usize;
+        if selected > index || selected >= self.level.collision_kinds.len() {
+            self.selected_collision_kind = self.selected_collision_kind.saturating_sub(1);
+        }
+        kind
+    }
+
+    /// Adds a collision kind to the end of the palette.
+    fn add_collision_kind(&mut self) {
+        let index = self.level.collision_kinds.len();
+        let name = (index + 1..)
+            .map(|n| format!("Kind {}", n))
+            .find(|name| {
+                !self
+                    .level
+                    .collision_kinds
+                    .iter()
+                    .any(|kind| &kind.name == name)
+            })
+            .unwrap();
+        let kind = CollisionKind::new(name, palette_color(index));
+        self.insert_collision_kind(index, kind);
+        self.current_mode = Mode::Collision;
+        self.push_action(Action::AddCollisionKind(index));
+    }
+
+    /// The palette `Mode::Collision` paints from. Kinds can be renamed and recolored, and removed
+    /// once nothing uses them.
+    fn collision_kinds_section(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Collision Kinds").show(ui, |ui| {
+            let used: HashSet<u16> = self
+                .level
+                .collision
+                .values()
+                .map(|cell| cell.kind)
+                .collect();
+            let only_one = self.level.collision_kinds.len() == 1;
+            let mut modified = false;
+            let mut removed = None;
+            for (index, kind) in self.level.collision_kinds.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    let selected = self.current_mode == Mode::Collision
+                        && self.selected_collision_kind as usize == index;
+                    if ui.radio(selected, "").on_hover_text("Paint with").clicked() {
+                        self.selected_collision_kind = index as u16;
+                        self.current_mode = Mode::Collision;
+                    }
+                    modified |= ui.color_edit_button_srgb(&mut kind.color).changed();
+                    modified |= ui
+                        .add(egui::TextEdit::singleline(&mut kind.name).desired_width(100.0))
+                        .changed();
+                    let removable = !only_one && !used.contains(&(index as u16));
+                    if ui
+                        .add_enabled(removable, egui::Button::new("🗙").small())
+                        .on_hover_text("Remove")
+                        .on_disabled_hover_text("Only kinds no collision uses can be removed")
+                        .clicked()
+                    {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if ui.button("Add Kind").clicked() {
+                self.add_collision_kind();
+            }
+            if modified {
+                self.mark_modified();
+            }
+            if let Some(index) = removed {
+                let kind = self.remove_collision_kind(index);
+                self.push_action(Action::RemoveCollisionKind(index, kind));
+            }
+        });
+    }
+
+    /// Applies the name typed for `renaming_layer`, leaving the old one if the new one is blank.
+    fn finish_renaming_layer(&mut self) {
+        if let Some((index, name)) = self.renaming_layer.take() {
+            let name = name.trim().to_string();
+            if !name.is_empty() && name != self.level.layers[index].name {
+                let old_name = std::mem::replace(&mut self.level.layers[index].name, name);
+                self.push_action(Action::RenameLayer(index, old_name));
+            }
+        }
+    }
+
+    fn layers_panel_row(&mut self, ui: &mut egui::Ui, index: usize) -> Option<LayerEdit> {
+        let mut edit = None;
+        ui.horizontal(|ui| {
+            let top = self.level.layers.len() - 1;
+            if ui
+                .add_enabled(index < top, egui::Button::new("⏶").small())
+                .on_hover_text("Move up")
+                .clicked()
+            {
+                edit = Some(LayerEdit::Move(index, index + 1));
+            }
+            if ui
+                .add_enabled(index > 0, egui::Button::new("⏷").small())
+                .on_hover_text("Move down")
+                .clicked()
+            {
+                edit = Some(LayerEdit::Move(index, index - 1));
+            }
+            if ui.small_button("🗙").on_hover_text("Remove").clicked() {
+                edit = Some(LayerEdit::Remove(index));
+            }
+            layer_view_controls(ui, &mut self.layer_views[index]);
+            match &mut self.renaming_layer {
+                Some((renaming, name)) if *renaming == index => {
+                    let response = ui.text_edit_singleline(name);
+                    if response.lost_focus() {
+                        if ui.input().key_pressed(egui::Key::Escape) {
+                            self.renaming_layer = None;
+                        } else {
+                            self.finish_renaming_layer();
+                        }
+                    } else if !response.has_focus() {
+                        response.request_focus();
+                    }
+                }
+                _ => {
+                    let selected =
+                        self.current_mode == Mode::DrawTiles && self.current_layer == index;
+                    let response = ui
+                        .selectable_label(selected, &self.level.layers[index].name)
+                        .on_hover_text("Double click to rename");
+                    if response.double_clicked() {
+                        self.renaming_layer = Some((index, self.level.layers[index].name.clone()));
+                    } else if response.clicked() {
+                        self.current_layer = index;
+                        self.current_mode = Mode::DrawTiles;
+                    }
+                }
+            }
+        });
+        edit
+    }
+
+    pub(crate) fn layers_panel(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::left("layers_panel").show(ctx, |ui| {
+            ui.label("Layers");
+            if ui.button("Add Layer").clicked() {
+                self.add_layer();
+            }
+            ui.separator();
+            ui.spacing_mut().slider_width = 48.0;
+            // drawn over every tile layer
+            ui.horizontal(|ui| {
+                layer_view_controls(ui, &mut self.entity_view);
+                ui.label("Entities");
+            });
+            ui.horizontal(|ui| {
+                layer_view_controls(ui, &mut self.collision_view);
+                ui.label("Collision");
+            });
+            self.collision_kinds_section(ui);
+            self.collision_shapes_section(ui);
+            self.int_grids_section(ui);
+            ui.separator();
+            let mut edit = None;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                // top layer first, the way they cover each other on the plot
+                for index in (0..self.level.layers.len()).rev() {
+                    if let Some(row_edit) = self.layers_panel_row(ui, index) {
+                        edit = Some(row_edit);
+                    }
+                }
+            });
+            match edit {
+                Some(LayerEdit::Remove(index)) => {
+                    let layer = self.remove_layer(index);
+                    self.push_action(Action::RemoveLayer(index, layer));
+                }
+                Some(LayerEdit::Move(from, to)) => {
+                    self.move_layer(from, to);
+                    self.push_action(Action::MoveLayer(from, to));
+                }
+                None => (),
+            }
+        });
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use eframe::egui;
+use rs_level::Level;
+
+mod autosave;
+mod collision_shapes;
+mod file;
+mod int_grids;
+
+mod render;
+pub use render::render_png;
+
+mod types;
+use types::*;
+
+mod layers_panel;
+mod plot_panel;
+mod popups;
+mod recent;
+mod side_panel;
+mod top_panel;
+mod unsaved;
+
+pub struct MyApp {
+    level: Level,
+    /// The file the level was opened from or last saved to.
+    level_path: Option<PathBuf>,
+    /// Length of the undo queue when the level matched `level_path`, `None` once that state
+    /// can't be returned to. See `MyApp::is_modified`.
+    saved_undo_len: Option<usize>,
+    window_title: String,
+    spritesheet_handle: Option<egui::TextureHandle>,
+    pending_spritesheet_path: Option<PathBuf>,
+    /// Where an opened level said its spritesheet was, when nothing was found there.
+    missing_spritesheet: Option<PathBuf>,
+    spritesheet_col_orientation: ColumnOrientation,
+    selected_tile: Option<TileIndex>,
+    selected_entity: Option<HashableVec2>,
+    entity_description: String,
+    prev_entity_description: String,
+    entity_descriptions: BTreeSet<String>,
+    current_mode: Mode,
+    /// Index into `Level::layers` of the layer `Mode::DrawTiles` draws on.
+    current_layer: usize,
+    /// One per layer in `Level::layers`, in the same order.
+    layer_views: Vec<LayerView>,
+    /// The layer whose name is being edited in the layers panel, and the edited name.
+    renaming_layer: Option<(usize, String)>,
+    /// Index into `Level::collision_kinds` of the kind `Mode::Collision` paints.
+    selected_collision_kind: u16,
+    /// The shape `Mode::Collision` paints.
+    selected_collision_shape: CollisionShape,
+    /// The corners typed for a custom polygon, see `collision_shapes::parse_polygon`.
+    custom_collision_polygon: String,
+    /// Index into `Level::int_grids` of the int grid `Mode::IntGrid` paints on.
+    current_int_grid: usize,
+    /// The value `Mode::IntGrid` paints, if the current int grid defines it.
+    selected_int_grid_value: Option<u32>,
+    /// One per int grid in `Level::int_grids`, in the same order.
+    int_grid_views: Vec<LayerView>,
+    /// The int grid whose name is being edited in the int grids section, and the edited name.
+    renaming_int_grid: Option<(usize, String)>,
+    undo_queue: Vec<Action>,
+    redo_queue: Vec<Action>,
+    show_entity_popup: bool,
+    show_clear_confirmation: bool,
+    unsaved_changes_prompt: Option<PendingAction>,
+    /// Set when the window should close on the next frame.
+    close_requested: bool,
+    /// Set once quitting with unsaved changes was confirmed, so closing isn't asked about again.
+    close_confirmed: bool,
+    error_message: Option<String>,
+    /// Title and lines of a popup listing what an import or export left out.
+    io_report: Option<(&'static str, Vec<String>)>,
+    /// Most recently opened or saved first.
+    recent_files: Vec<PathBuf>,
+    /// Where `recent_files` is kept between sessions, `None` to not keep it.
+    recent_files_path: Option<PathBuf>,
+    /// Where autosaves go, `None` when autosave is off, e.g. in `lvl-tool`.
+    recovery_path: Option<PathBuf>,
+    /// Held from when autosave starts until a clean exit, telling other sessions that
+    /// `recovery_path` isn't theirs to recover.
+    recovery_lock: Option<(PathBuf, std::fs::File)>,
+    /// Recovery files other sessions left behind, offered for restoring until the prompt closes.
+    recovery_files: Vec<PathBuf>,
+    last_autosave: Instant,
+    /// Hash of what the recovery file holds, to skip autosaves that wouldn't change it.
+    last_autosave_hash: Option<u64>,
+    collision_view: LayerView,
+    entity_view: LayerView,
+    show_grid: bool,
+}
+
+impl Default for MyApp {
+    fn default() -> Self {
+        Self {
+            level: Level::default(),
+            level_path: None,
+            saved_undo_len: Some(0),
+            window_title: String::new(),
+            spritesheet_handle: None,
+            pending_spritesheet_path: None,
+            missing_spritesheet: None,
+            spritesheet_col_orientation: ColumnOrientation::Minor,
+            selected_tile: None,
+            selected_entity: None,
+            entity_description: "".to_string(),
+            prev_entity_description: "".to_string(),
+            entity_descriptions: BTreeSet::new(),
+            current_mode: Mode::DrawTiles,
+            current_layer: 0,
+            layer_views: vec![LayerView::default(); Level::default().layers.len()],
+            renaming_layer: None,
+            selected_collision_kind: 0,
+            selected_collision_shape: CollisionShape::Full,
+            custom_collision_polygon: String::new(),
+            current_int_grid: 0,
+            selected_int_grid_value: None,
+            int_grid_views: Vec::new(),
+            renaming_int_grid: None,
+            undo_queue: Vec::new(),
+            redo_queue: Vec::new(),
+            show_entity_popup: false,
+            show_clear_confirmation: false,
+            unsaved_changes_prompt: None,
+            close_requested: false,
+            close_confirmed: false,
+            error_message: None,
+            io_report: None,
+            recent_files: Vec::new(),
+            recent_files_path: None,
+            recovery_path: None,
+            recovery_lock: None,
+            recovery_files: Vec::new(),
+            last_autosave: Instant::now(),
+            last_autosave_hash: None,
+            collision_view: LayerView::default(),
+            entity_view: LayerView::default(),
+            show_grid: true,
+        }
+    }
+}
+
+/// Undoes a click that placed `placed` at `point`, replacing `replaced`, in tiles, collision or
+/// an int grid. Returns what the action that redoes it placed and replaced.
+fn undo_cell_click<T: Clone>(
+    cells: &mut HashMap<HashableVec2, T>,
+    point: HashableVec2,
+    placed: T,
+    replaced: Option<T>,
+    is_drag: bool,
+) -> (T, Option<T>) {
+    match replaced {
+        Some(old) if is_drag => {
+            cells.insert(point, old.clone());
+            (old, Some(placed))
+        }
+        // a click either placed a cell or removed the one there
+        replaced => {
+            if let Entry::Vacant(e) = cells.entry(point) {
+                e.insert(replaced.clone().unwrap_or_else(|| placed.clone()));
+            } else {
+                cells.remove(&point);
+            }
+            (placed, replaced)
+        }
+    }
+}
+
+impl MyApp {
+    /// The editor as started from the desktop. Unlike `MyApp::default`, which `lvl-tool` uses, it
+    /// autosaves and remembers recently used files.
+    pub fn new() -> Self {
+        let mut app = Self::default();
+        app.enable_autosave();
+        app.load_recent_files();
+        app
+    }
+    fn clear(&mut self) {
+        // clearing a level that is already empty and saved changes nothing
+        let unchanged = !self.is_modified() && self.level.is_empty();
+        self.show_clear_confirmation = false;
+        self.renaming_layer = None;
+        self.renaming_int_grid = None;
+        for layer in &mut self.level.layers {
+            layer.tiles.clear();
+        }
+        self.level.collision.clear();
+        for layer in &mut self.level.int_grids {
+            layer.cells.clear();
+        }
+        self.entity_descriptions.clear();
+        self.level.entities.clear();
+        self.undo_queue.clear();
+        self.redo_queue.clear();
+        self.saved_undo_len = unchanged.then_some(0);
+    }
+    fn handle_undo_redo(&mut self, is_undo: bool) {
+        let queue = if is_undo {
+            &mut self.undo_queue
+        } else {
+            &mut self.redo_queue
+        };
+        if let Some(action) = queue.pop() {
+            let mut cloned_action = action.clone();
+            match action {
+                Action::ClickTile(layer, point, tile, old_tile_maybe, is_drag) => {
+                    let tiles = &mut self.level.layers[layer].tiles;
+                    let (tile, old_tile_maybe) =
+                        undo_cell_click(tiles, point, tile, old_tile_maybe, is_drag);
+                    cloned_action = Action::ClickTile(layer, point, tile, old_tile_maybe, is_drag);
+                }
+                Action::ClickCollision(point, cell, old_cell_maybe, is_drag) => {
+                    let collision = &mut self.level.collision;
+                    let (cell, old_cell_maybe) =
+                        undo_cell_click(collision, point, cell, old_cell_maybe, is_drag);
+                    cloned_action = Action::ClickCollision(point, cell, old_cell_maybe, is_drag);
+                }
+                Action::ClickEntity(point, attached_label) => {
+                    if let Some(label) = self.level.entities.get(&point) {
+                        let label_clone = label.clone();
+                        self.entity_descriptions.remove(label);
+                        self.level.entities.remove(&point);
+                        cloned_action = Action::ClickEntity(point, Some(label_clone));
+                    } else if let Some(label) = attached_label {
+                        self.level.entities.insert(point, label.clone());
+                        self.entity_descriptions.insert(label.clone());
+                    }
+                }
+                Action::AddLayer(index) => {
+                    let layer = self.remove_layer(index);
+                    cloned_action = Action::RemoveLayer(index, layer);
+                }
+                Action::RemoveLayer(index, layer) => {
+                    self.insert_layer(index, layer);
+                    cloned_action = Action::AddLayer(index);
+                }
+                Action::RenameLayer(index, name) => {
+                    let current = std::mem::replace(&mut self.level.layers[index].name, name);
+                    cloned_action = Action::RenameLayer(index, current);
+                }
+                Action::MoveLayer(from, to) => {
+                    self.move_layer(to, from);
+                    cloned_action = Action::MoveLayer(to, from);
+                }
+                Action::AddCollisionKind(index) => {
+                    let kind = self.remove_collision_kind(index);
+                    cloned_action = Action::RemoveCollisionKind(index, kind);
+                }
+                Action::RemoveCollisionKind(index, kind) => {
+                    self.insert_collision_kind(index, kind);
+                    cloned_action = Action::AddCollisionKind(index);
+                }
+                Action::ClickIntGrid(layer, point, value, old_value_maybe, is_drag) => {
+                    let cells = &mut self.level.int_grids[layer].cells;
+                    let (value, old_value_maybe) =
+                        undo_cell_click(cells, point, value, old_value_maybe, is_drag);
+                    cloned_action =
+                        Action::ClickIntGrid(layer, point, value, old_value_maybe, is_drag);
+                }
+                Action::AddIntGrid(index) => {
+                    let layer = self.remove_int_grid(index);
+                    cloned_action = Action::RemoveIntGrid(index, layer);
+                }
+                Action::RemoveIntGrid(index, layer) => {
+                    self.insert_int_grid(index, layer);
+                    cloned_action = Action::AddIntGrid(index);
+                }
+                Action::AddIntGridValue(layer, index) => {
+                    let value = self.level.int_grids[layer].values.remove(index);
+                    // paint with another value if that one was selected
+                    self.select_int_grid(self.current_int_grid);
+                    cloned_action = Action::RemoveIntGridValue(layer, index, value);
+                }
+                Action::RenameIntGrid(index, name) => {
+                    let current = std::mem::replace(&mut self.level.int_grids[index].name, name);
+                    cloned_action = Action::RenameIntGrid(index, current);
+                }
+                Action::RemoveIntGridValue(layer, index, value) => {
+                    self.level.int_grids[layer].values.insert(index, value);
+                    cloned_action = Action::AddIntGridValue(layer, index);
+                }
+            };
+            if is_undo {
+                self.redo_queue.push(cloned_action);
+            } else {
+                self.undo_queue.push(cloned_action);
+            }
+        }
+    }
+    fn toggle_current_mode(&mut self) {
+        // every layer in turn, bottom first, then collision, every int grid and entities
+        self.current_mode = match self.current_mode {
+            Mode::DrawTiles if self.current_layer + 1 < self.level.layers.len() => {
+                self.current_layer += 1;
+                Mode::DrawTiles
+            }
+            Mode::DrawTiles => Mode::Collision,
+            Mode::Collision if !self.level.int_grids.is_empty() => {
+                self.select_int_grid(0);
+                Mode::IntGrid
+            }
+            Mode::IntGrid if self.current_int_grid + 1 < self.level.int_grids.len() => {
+                self.select_int_grid(self.current_int_grid + 1);
+                Mode::IntGrid
+            }
+            Mode::Collision | Mode::IntGrid => Mode::Entity,
+            Mode::Entity if self.level.layers.is_empty() => Mode::Collision,
+            Mode::Entity => {
+                self.current_layer = 0;
+                Mode::DrawTiles
+            }
+        }
+    }
+    fn handle_toplevel_input(&mut self, ctx: &egui::Context) {
+        if self.show_clear_confirmation
+            || self.unsaved_changes_prompt.is_some()
+            || !self.recovery_files.is_empty()
+            || self.show_entity_popup
+            || self.error_message.is_some()
+            || self.io_report.is_some()
+            || self.renaming_layer.is_some()
+            || self.renaming_int_grid.is_some()
+        {
+            return;
+        }
+        for event in &ctx.input().events {
+            if let egui::Event::Key {
+                key,
+                pressed,
+                modifiers,
+            } = event
+            {
+                if !*pressed {
+                    match (key, modifiers) {
+                        (egui::Key::M, _) => self.toggle_current_mode(),
+                        (egui::Key::Z, egui::Modifiers { ctrl, .. }) => {
+                            if *ctrl {
+                                self.handle_undo_redo(true);
+                            }
+                        }
+                        (egui::Key::R, egui::Modifiers { ctrl, .. }) => {
+                            if *ctrl {
+                                self.handle_undo_redo(false);
+                            }
+                        }
+                        (egui::Key::O, egui::Modifiers { ctrl, .. }) => {
+                            if *ctrl {
+                                self.request(PendingAction::Open);
+                            }
+                        }
+                        (egui::Key::S, egui::Modifiers { ctrl, shift, .. }) => {
+                            if *ctrl && *shift {
+                                self.save_level_as();
+                            } else if *ctrl {
+                                self.save_level();
+                            }
+                        }
+                        _ => (),
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.handle_recovery_popup(ctx);
+        self.handle_unsaved_changes_popup(ctx);
+        self.handle_clear_confirmation_popup(ctx);
+        self.handle_entity_popup(ctx);
+        self.handle_error_popup(ctx);
+        self.handle_io_report_popup(ctx);
+        self.handle_toplevel_input(ctx);
+        self.top_panel(ctx);
+        self.side_panel(ctx);
+        self.layers_panel(ctx);
+        self.plot_panel(ctx);
+        self.autosave();
+        self.update_window_title(frame);
+        self.handle_close_request(frame);
+    }
+
+    fn on_close_event(&mut self) -> bool {
+        if self.close_confirmed || !self.is_modified() {
+            return true;
+        }
+        self.unsaved_changes_prompt = Some(PendingAction::Close);
+        false
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.remove_recovery_file();
+    }
+}
@@ -1,11 +1,66 @@
 use eframe::egui;
-use egui::{Rect, Vec2};
+use egui::Vec2;
 use std::collections::HashMap;
 
 use crate::types::*;
 use crate::MyApp;
 
+/// How much of its opacity a layer keeps while another one is soloed.
+const SOLO_DIMMING: f32 = 0.2;
+
+/// Paints `selected` at `point` in tiles, collision or an int grid. A click removes whatever is
+/// there or places `selected` in an empty cell, while dragging paints over the cells it passes.
+/// Returns the cell it replaced when anything changed, for the undo queue.
+fn paint_cell<T: Clone + PartialEq>(
+    cells: &mut HashMap<HashableVec2, T>,
+    point: HashableVec2,
+    selected: T,
+    is_drag: bool,
+) -> Option<Option<T>> {
+    if !is_drag {
+        let original = cells.remove(&point);
+        if original.is_none() {
+            cells.insert(point, selected);
+        }
+        Some(original)
+    } else {
+        match cells.insert(point, selected.clone()) {
+            Some(original) if original == selected => None,
+            original => Some(original),
+        }
+    }
+}
+
 impl MyApp {
+    /// Whether clicks in the current mode would change a locked layer.
+    fn current_layer_locked(&self) -> bool {
+        match self.current_mode {
+            Mode::DrawTiles => self
+                .layer_views
+                .get(self.current_layer)
+                .is_some_and(|view| view.locked),
+            Mode::Collision => self.collision_view.locked,
+            Mode::IntGrid => self
+                .int_grid_views
+                .get(self.current_int_grid)
+                .is_some_and(|view| view.locked),
+            Mode::Entity => self.entity_view.locked,
+        }
+    }
+    /// The opacity `view` is drawn with, dimmed if other layers are soloed.
+    fn shown_opacity(&self, view: &LayerView) -> f32 {
+        let any_solo = self
+            .layer_views
+            .iter()
+            .chain(&self.int_grid_views)
+            .chain([&self.collision_view, &self.entity_view])
+            .any(|view| view.solo);
+        if any_solo && !view.solo {
+            view.opacity * SOLO_DIMMING
+        } else {
+            view.opacity
+        }
+    }
     fn handle_plot_clicks(
         &mut self,
         plot_ui: &egui::plot::PlotUi,
@@ -26,18 +81,26 @@ impl MyApp {
             let plot_bounds = plot_ui.plot_bounds();
             let min = plot_bounds.min();
             let max = plot_bounds.max();
-            let hashable_point = HashableVec2::from(point);
+            let hashable_point = plot_cell(point);
             if !(coord.x < min[0]
                 || coord.x > max[0]
                 || coord.y < min[1]
                 || coord.y > max[1]
                 || self.show_clear_confirmation
-                || self.show_entity_popup)
+                || self.unsaved_changes_prompt.is_some()
+                || !self.recovery_files.is_empty()
+                || self.show_entity_popup
+                || self.error_message.is_some()
+                || self.io_report.is_some())
             // stop when pop ups are open
             {
+                // a locked layer can still be picked from with a right click, just not painted
+                let locked = self.current_layer_locked();
+                let primary_clicked = primary_clicked && !locked;
+                let is_drag = is_drag && !locked;
                 match self.current_mode {
-                    Mode::DrawForeground | Mode::DrawBackground => {
-                        self.handle_plot_fg_bg_clicks(
+                    Mode::DrawTiles => {
+                        self.handle_plot_tile_clicks(
                             primary_clicked,
                             secondary_clicked,
                             is_drag,
@@ -45,8 +108,23 @@ impl MyApp {
                         );
                     }
                     Mode::Collision => {
-                        self.handle_plot_collision_clicks(primary_clicked, is_drag, hashable_point);
+                        self.handle_plot_collision_clicks(
+                            primary_clicked,
+                            secondary_clicked,
+                            is_drag,
+                            hashable_point,
+                        );
                     }
+                    Mode::IntGrid => {
+                        self.handle_plot_int_grid_clicks(
+                            primary_clicked,
+                            secondary_clicked,
+                            is_drag,
+                            hashable_point,
+                        );
+                    }
+                    // right clicks open an entity's label for editing, so they are locked out too
+                    Mode::Entity if locked => {}
                     Mode::Entity => {
                         self.handle_plot_entity_clicks(
                             primary_clicked,
@@ -59,108 +137,106 @@ impl MyApp {
             }
         }
     }
-    fn handle_plot_fg_bg_clicks(
+    fn handle_plot_tile_clicks(
         &mut self,
         primary_clicked: bool,
         secondary_clicked: bool,
         is_drag: bool,
         hashable_point: HashableVec2,
     ) {
-        if let Some(selected_uv) = self.selected_uv {
-            let (layer_plotted_tiles, mut action) = match self.current_mode {
-                Mode::DrawBackground => (
-                    &mut self.background_plotted_tiles,
-                    Action::ClickBackground(hashable_point, selected_uv, None, is_drag),
-                ),
-                Mode::DrawForeground => (
-                    &mut self.foreground_plotted_tiles,
-                    Action::ClickForeground(hashable_point, selected_uv, None, is_drag),
-                ),
-                _ => unreachable!(),
-            };
+        let layer = self.current_layer;
+        let layer_plotted_tiles = match self.level.layers.get_mut(layer) {
+            Some(layer) => &mut layer.tiles,
+            None => return,
+        };
+        if let Some(selected_tile) = self.selected_tile {
             if primary_clicked || is_drag {
-                if !is_drag {
-                    if let Some(original_uv) = layer_plotted_tiles.remove(&hashable_point) {
-                        action = match self.current_mode {
-                            Mode::DrawBackground => Action::ClickBackground(
-                                hashable_point,
-                                selected_uv,
-                                Some(original_uv),
-                                is_drag,
-                            ),
-                            Mode::DrawForeground => Action::ClickForeground(
-                                hashable_point,
-                                selected_uv,
-                                Some(original_uv),
-                                is_drag,
-                            ),
-                            _ => unreachable!(),
-                        };
-                    } else {
-                        layer_plotted_tiles.insert(hashable_point, selected_uv);
-                    }
-                    self.undo_queue.push(action);
-                    self.redo_queue.clear();
-                } else if let Some(original_uv) =
-                    layer_plotted_tiles.insert(hashable_point, selected_uv)
+                if let Some(original_tile) =
+                    paint_cell(layer_plotted_tiles, hashable_point, selected_tile, is_drag)
                 {
-                    if original_uv != selected_uv {
-                        action = match self.current_mode {
-                            Mode::DrawBackground => Action::ClickBackground(
-                                hashable_point,
-                                selected_uv,
-                                Some(original_uv),
-                                is_drag,
-                            ),
-                            Mode::DrawForeground => Action::ClickForeground(
-                                hashable_point,
-                                selected_uv,
-                                Some(original_uv),
-                                is_drag,
-                            ),
-                            _ => unreachable!(),
-                        };
-                        self.undo_queue.push(action);
-                        self.redo_queue.clear();
-                    }
-                } else {
-                    self.undo_queue.push(action);
-                    self.redo_queue.clear();
+                    self.push_action(Action::ClickTile(
+                        layer,
+                        hashable_point,
+                        selected_tile,
+                        original_tile,
+                        is_drag,
+                    ));
                 }
             }
         }
         if secondary_clicked {
-            match self.current_mode {
-                Mode::DrawBackground => {
-                    if let Some(uv) = self.background_plotted_tiles.get(&hashable_point) {
-                        self.selected_uv = Some(*uv);
-                    }
-                }
-                Mode::DrawForeground => {
-                    if let Some(uv) = self.foreground_plotted_tiles.get(&hashable_point) {
-                        self.selected_uv = Some(*uv);
-                    }
-                }
-                _ => unreachable!(),
-            };
+            if let Some(tile) = self.level.layers[layer].tiles.get(&hashable_point) {
+                self.selected_tile = Some(*tile);
+            }
         }
     }
     fn handle_plot_collision_clicks(
         &mut self,
         primary_clicked: bool,
+        secondary_clicked: bool,
         is_drag: bool,
         hashable_point: HashableVec2,
     ) {
-        if primary_clicked || is_drag {
-            if !is_drag {
-                if !self.collision_tiles.remove(&hashable_point) {
-                    self.collision_tiles.insert(hashable_point);
+        let cell = CollisionCell {
+            kind: self.selected_collision_kind,
+            shape: self.selected_collision_shape.clone(),
+        };
+        // a level may come with an empty palette, until a kind is added
+        if (primary_clicked || is_drag) && (cell.kind as usize) < self.level.collision_kinds.len() {
+            if let Some(original_cell) = paint_cell(
+                &mut self.level.collision,
+                hashable_point,
+                cell.clone(),
+                is_drag,
+            ) {
+                self.push_action(Action::ClickCollision(
+                    hashable_point,
+                    cell,
+                    original_cell,
+                    is_drag,
+                ));
+            }
+        }
+        if secondary_clicked {
+            if let Some(cell) = self.level.collision.get(&hashable_point).cloned() {
+                self.selected_collision_kind = cell.kind;
+                self.select_collision_shape(cell.shape);
+            }
+        }
+    }
+    fn handle_plot_int_grid_clicks(
+        &mut self,
+        primary_clicked: bool,
+        secondary_clicked: bool,
+        is_drag: bool,
+        hashable_point: HashableVec2,
+    ) {
+        let layer = self.current_int_grid;
+        let int_grid = match self.level.int_grids.get_mut(layer) {
+            Some(int_grid) => int_grid,
+            None => return,
+        };
+        let selected_value = self
+            .selected_int_grid_value
+            .filter(|value| int_grid.value(*value).is_some());
+        if let Some(value) = selected_value {
+            if primary_clicked || is_drag {
+                if let Some(original_value) =
+                    paint_cell(&mut int_grid.cells, hashable_point, value, is_drag)
+                {
+                    self.push_action(Action::ClickIntGrid(
+                        layer,
+                        hashable_point,
+                        value,
+                        original_value,
+                        is_drag,
+                    ));
                 }
-                self.undo_queue.push(Action::ClickCollision(hashable_point));
-                self.redo_queue.clear();
-            } else if self.collision_tiles.insert(hashable_point) {
-                self.undo_queue.push(Action::ClickCollision(hashable_point));
-                self.redo_queue.clear();
+            }
+        }
+        if secondary_clicked {
+            if let Some(value) = self.level.int_grids[layer].cells.get(&hashable_point) {
+                self.selected_int_grid_value = Some(*value);
             }
         }
     }
@@ -174,21 +250,19 @@ impl MyApp {
         if primary_clicked || is_drag {
             if !is_drag {
                 let mut action_label = None;
-                if let Some(label) = self.entity_tiles.remove(&hashable_point) {
+                if let Some(label) = self.level.entities.remove(&hashable_point) {
                     action_label = Some(label.clone());
                     self.entity_descriptions.remove(&label);
                 } else {
-                    self.entity_tiles.insert(hashable_point, "".to_owned());
+                    self.level.entities.insert(hashable_point, "".to_owned());
                     self.show_entity_popup = true;
                     self.prev_entity_description = "".to_owned();
                     self.selected_entity = Some(hashable_point);
                 }
-                self.undo_queue
-                    .push(Action::ClickEntity(hashable_point, action_label));
-                self.redo_queue.clear();
+                self.push_action(Action::ClickEntity(hashable_point, action_label));
             }
         } else if secondary_clicked {
-            if let Some(description) = self.entity_tiles.get(&hashable_point) {
+            if let Some(description) = self.level.entities.get(&hashable_point) {
                 self.show_entity_popup = true;
                 self.entity_description = description.clone();
                 self.prev_entity_description = self.entity_description.clone();
@@ -200,10 +274,12 @@ impl MyApp {
         &self,
         plot_ui: &mut egui::plot::PlotUi,
         handle: &egui::TextureHandle,
-        plotted_tiles: &HashMap<HashableVec2, Rect>,
+        plotted_tiles: &HashMap<HashableVec2, TileIndex>,
+        opacity: f32,
     ) {
         let handle_size = handle.size_vec2();
-        for (point, uv) in plotted_tiles {
+        let tint = egui::Color32::WHITE.linear_multiply(opacity);
+        for (point, tile) in plotted_tiles {
             let final_coord = egui::widgets::plot::PlotPoint {
                 x: point.x as f64 + 0.5,
                 y: point.y as f64 + 0.5,
@@ -212,41 +288,119 @@ impl MyApp {
                 handle,
                 final_coord,
                 Vec2 {
-                    x: handle_size.x / self.spritesheet_info.num_rows as f32,
-                    y: handle_size.y / self.spritesheet_info.num_cols as f32,
-                } / self.spritesheet_info.sprite_size as f32,
+                    x: handle_size.x / self.level.spritesheet_info.num_rows as f32,
+                    y: handle_size.y / self.level.spritesheet_info.num_cols as f32,
+                } / self.level.spritesheet_info.sprite_size as f32,
             )
-            .uv(*uv);
+            .uv(tile_uv(&self.level.spritesheet_info, *tile))
+            .tint(tint);
             plot_ui.image(img);
         }
     }
+    /// Stands in for sprites until a spritesheet is loaded, coloring each cell by its tile index
+    /// so different tiles can still be told apart.
+    fn draw_placeholders_on_plot(
+        &self,
+        plot_ui: &mut egui::plot::PlotUi,
+        plotted_tiles: &HashMap<HashableVec2, TileIndex>,
+        opacity: f32,
+    ) {
+        for (point, tile) in plotted_tiles {
+            let (x, y) = (point.x as f64, point.y as f64);
+            let n = tile.row as f32 * self.level.spritesheet_info.num_rows as f32 + tile.col as f32;
+            let hue = (n * 0.618_034).fract();
+            let color = egui::Color32::from(egui::color::Hsva::new(hue, 0.6, 0.8, 1.0));
+            let cell = egui::plot::Polygon::new(vec![
+                [x, y],
+                [x + 1.0, y],
+                [x + 1.0, y + 1.0],
+                [x, y + 1.0],
+            ])
+            .color(color.linear_multiply(opacity))
+            .fill_alpha(0.5 * opacity);
+            plot_ui.polygon(cell);
+        }
+    }
+    /// Fills every cell of `int_grid` with the color of its value.
+    fn draw_int_grid_on_plot(
+        &self,
+        plot_ui: &mut egui::plot::PlotUi,
+        int_grid: &IntGridLayer,
+        opacity: f32,
+    ) {
+        for (point, value) in &int_grid.cells {
+            let [r, g, b] = match int_grid.value(*value) {
+                Some(value) => value.color,
+                None => continue,
+            };
+            let (x, y) = (point.x as f64, point.y as f64);
+            let cell = egui::plot::Polygon::new(vec![
+                [x, y],
+                [x + 1.0, y],
+                [x + 1.0, y + 1.0],
+                [x, y + 1.0],
+            ])
+            .color(egui::Color32::from_rgb(r, g, b).linear_multiply(opacity))
+            .fill_alpha(0.4 * opacity);
+            plot_ui.polygon(cell);
+        }
+    }
     fn draw_on_plot(&mut self, plot_ui: &mut egui::plot::PlotUi) {
         // if we want to draw sprites, we need a spritesheet
-        if let Some(handle) = &self.spritesheet_handle {
-            if self.show_background {
-                self.draw_sprites_on_plot(plot_ui, handle, &self.background_plotted_tiles);
-            }
-            if self.show_foreground {
-                self.draw_sprites_on_plot(plot_ui, handle, &self.foreground_plotted_tiles);
+        let visible_layers = self
+            .level
+            .layers
+            .iter()
+            .zip(&self.layer_views)
+            .filter(|(_, view)| view.visible);
+        for (layer, view) in visible_layers {
+            let opacity = self.shown_opacity(view);
+            if let Some(handle) = &self.spritesheet_handle {
+                self.draw_sprites_on_plot(plot_ui, handle, &layer.tiles, opacity);
+            } else {
+                self.draw_placeholders_on_plot(plot_ui, &layer.tiles, opacity);
             }
         }
         // can draw these without spritesheet
-        if self.show_collision {
-            let collision_plot_points: Vec<[f64; 2]> = self
-                .collision_tiles
-                .iter()
-                .map(|point| [point.x as f64 + 0.5, point.y as f64 + 0.5])
-                .collect();
-            let collision_points = egui::plot::Points::new(collision_plot_points)
-                .filled(false)
-                .radius(10.0)
-                .shape(egui::plot::MarkerShape::Square)
-                .color(egui::Color32::from_rgb(255, 0, 0));
-            plot_ui.points(collision_points);
+        let visible_int_grids = self
+            .level
+            .int_grids
+            .iter()
+            .zip(&self.int_grid_views)
+            .filter(|(_, view)| view.visible);
+        for (int_grid, view) in visible_int_grids {
+            self.draw_int_grid_on_plot(plot_ui, int_grid, self.shown_opacity(view));
+        }
+        if self.collision_view.visible {
+            let opacity = self.shown_opacity(&self.collision_view);
+            for (point, cell) in &self.level.collision {
+                let [r, g, b] = match self.level.collision_kinds.get(cell.kind as usize) {
+                    Some(kind) => kind.color,
+                    None => continue,
+                };
+                // shrunk a little toward the middle of the cell, so neighbours stay apart
+                let mut outline: Vec<[f64; 2]> = cell
+                    .shape
+                    .outline()
+                    .into_iter()
+                    .map(|[x, y]| {
+                        [
+                            point.x as f64 + 0.5 + (x as f64 - 0.5) * 0.9,
+                            point.y as f64 + 0.5 + (y as f64 - 0.5) * 0.9,
+                        ]
+                    })
+                    .collect();
+                outline.push(outline[0]);
+                let line = egui::plot::Line::new(outline)
+                    .width(2.0)
+                    .color(egui::Color32::from_rgb(r, g, b).linear_multiply(opacity));
+                plot_ui.line(line);
+            }
         }
-        if self.show_entity {
+        if self.entity_view.visible {
             let entity_plot_points: Vec<[f64; 2]> = self
-                .entity_tiles
+                .level
+                .entities
                 .keys()
                 .map(|point| [point.x as f64 + 0.5, point.y as f64 + 0.5])
                 .collect();
@@ -254,7 +408,10 @@ impl MyApp {
                 .filled(false)
                 .radius(10.0)
                 .shape(egui::plot::MarkerShape::Diamond)
-                .color(egui::Color32::from_rgb(0, 255, 255));
+                .color(
+                    egui::Color32::from_rgb(0, 255, 255)
+                        .linear_multiply(self.shown_opacity(&self.entity_view)),
+                );
             plot_ui.points(entity_points);
         }
     }
@@ -305,7 +462,7 @@ impl MyApp {
                     && drag_delta.y > -0.05
                     && drag_delta.y < 0.05);
                 self.handle_plot_clicks(plot_ui, primary_clicked, secondary_clicked, is_drag);
-                // Draw sprites, background then foreground
+                // Draw sprites, bottom layer first
                 self.draw_on_plot(plot_ui);
             });
         });
@@ -1,5 +1,9 @@
+use std::path::Path;
+
 use eframe::egui;
 
+use crate::autosave::recovery_file_age;
+use crate::file::LevelIoError;
 use crate::types::*;
 use crate::MyApp;
 
@@ -21,6 +25,125 @@ impl MyApp {
                 });
         }
     }
+    pub(crate) fn handle_recovery_popup(&mut self, ctx: &egui::Context) {
+        if !self.recovery_files.is_empty() {
+            let mut restored = None;
+            let mut discarded = None;
+            let mut later = false;
+            egui::Window::new("Earlier sessions didn't exit cleanly. Restore an autosave?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    for path in &self.recovery_files {
+                        ui.horizontal(|ui| {
+                            let name = path.file_name().unwrap_or_default().to_string_lossy();
+                            ui.label(format!("{}, {}", name, recovery_file_age(path)));
+                            if ui.button("Discard").clicked() {
+                                discarded = Some(path.clone());
+                            }
+                            if ui.button("Restore").clicked() {
+                                restored = Some(path.clone());
+                            }
+                        });
+                    }
+                    ui.separator();
+                    if ui
+                        .button("Later")
+                        .on_hover_text("Keep them to decide next time")
+                        .clicked()
+                    {
+                        later = true;
+                    }
+                });
+            if let Some(path) = discarded {
+                self.discard_recovery_file(&path);
+            }
+            if let Some(path) = restored {
+                self.restore_recovery_file(path);
+            }
+            if later {
+                self.recovery_files.clear();
+            }
+        }
+    }
+    pub(crate) fn handle_unsaved_changes_popup(&mut self, ctx: &egui::Context) {
+        if let Some(action) = self.unsaved_changes_prompt.clone() {
+            egui::Window::new("Save changes before continuing?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            self.unsaved_changes_prompt = None;
+                        }
+                        if ui.button("Discard").clicked() {
+                            self.continue_with(action.clone());
+                        }
+                        if ui.button("Save").clicked() && self.save_level() {
+                            self.continue_with(action.clone());
+                        }
+                    });
+                });
+        }
+    }
+    /// Shows a failed level IO operation in the error popup, e.g. "Couldn't save level.lvl: ..."
+    pub(crate) fn report_io_error(
+        &mut self,
+        action: &str,
+        path: &Path,
+        result: Result<(), LevelIoError>,
+    ) {
+        if let Err(why) = result {
+            self.error_message = Some(format!("Couldn't {} {}: {}", action, path.display(), why));
+        }
+    }
+    pub(crate) fn handle_error_popup(&mut self, ctx: &egui::Context) {
+        if let Some(message) = &self.error_message {
+            let mut dismissed = false;
+            egui::Window::new("Error")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(message);
+                    if ui.button("Ok").clicked() {
+                        dismissed = true;
+                    }
+                });
+            if dismissed {
+                self.error_message = None;
+            }
+        }
+    }
+    /// Lists what an import or export left out, if anything.
+    pub(crate) fn show_io_report(&mut self, title: &'static str, report: Vec<String>) {
+        if !report.is_empty() {
+            self.io_report = Some((title, report));
+        }
+    }
+    pub(crate) fn handle_io_report_popup(&mut self, ctx: &egui::Context) {
+        if let Some((title, report)) = &self.io_report {
+            let mut dismissed = false;
+            egui::Window::new(*title)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for line in report {
+                                ui.label(line);
+                            }
+                        });
+                    ui.separator();
+                    if ui.button("Ok").clicked() {
+                        dismissed = true;
+                    }
+                });
+            if dismissed {
+                self.io_report = None;
+            }
+        }
+    }
     // TODO maybe pass the entity key in rather than getting it inside
     fn entity_description_is_ok(&self) -> (bool, HashableVec2) {
         if let Some(entity_key) = self.selected_entity {
@@ -36,9 +159,14 @@ impl MyApp {
         if self.prev_entity_description != self.entity_description {
             self.entity_descriptions
                 .remove(&self.prev_entity_description);
+            // renames aren't in the undo queue
+            if !self.prev_entity_description.is_empty() {
+                self.mark_modified();
+            }
         }
         self.show_entity_popup = false;
-        self.entity_tiles
+        self.level
+            .entities
             .insert(entity_key, self.entity_description.clone());
         self.entity_descriptions
             .insert(self.entity_description.clone());
@@ -74,7 +202,7 @@ impl MyApp {
                         if ui.button("Cancel").clicked() {
                             let (description_is_ok, entity_key) = self.entity_description_is_ok();
                             if (!description_is_ok) || self.prev_entity_description.is_empty() {
-                                self.entity_tiles.remove(&entity_key);
+                                self.level.entities.remove(&entity_key);
                             }
                             self.show_entity_popup = false;
                             self.entity_description = "".to_string();
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use crate::file::data_dir;
+use crate::MyApp;
+
+const MAX_RECENT_FILES: usize = 10;
+
+impl MyApp {
+    /// Reads the recent files list kept by earlier sessions, one path per line.
+    pub(crate) fn load_recent_files(&mut self) {
+        self.recent_files_path = data_dir().map(|dir| dir.join("recent_files.txt"));
+        if let Some(text) = self
+            .recent_files_path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+        {
+            self.recent_files = text
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .take(MAX_RECENT_FILES)
+                .collect();
+        }
+    }
+
+    /// Moves `path` to the top of the recent files list and writes the list out.
+    pub(crate) fn remember_recent_file(&mut self, path: PathBuf) {
+        let path = path.canonicalize().unwrap_or(path);
+        self.recent_files.retain(|recent| *recent != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+        self.write_recent_files();
+    }
+
+    pub(crate) fn clear_recent_files(&mut self) {
+        self.recent_files.clear();
+        self.write_recent_files();
+    }
+
+    fn write_recent_files(&self) {
+        let path = match &self.recent_files_path {
+            Some(path) => path,
+            None => return,
+        };
+        let mut text = String::new();
+        for recent in &self.recent_files {
+            text.push_str(&recent.to_string_lossy());
+            text.push('\n');
+        }
+        // losing the list isn't worth interrupting anyone over
+        let _ = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, text));
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use egui::epaint::text::{FontDefinitions, Fonts};
+use egui::epaint::ImageData;
+use egui::{Color32, FontId};
+use image::{GenericImageView, Rgba, RgbaImage};
+use rs_level::cli::RenderOptions;
+use rs_level::Level;
+
+use crate::file::LevelIoError;
+use crate::types::{IntGridLayer, LevelBounds};
+use crate::{HashableVec2, MyApp, TileIndex};
+
+const ENTITY_COLOR: Rgba<u8> = Rgba([0, 255, 255, 255]);
+const LABEL_FONT_SIZE: f32 = 12.0;
+/// Renders larger than this, 256 MiB of RGBA, are refused rather than allocated.
+const MAX_RENDER_PIXELS: u64 = 1 << 26;
+
+/// Image errors are reported like any other failed level IO.
+fn image_error(why: image::ImageError) -> LevelIoError {
+    match why {
+        image::ImageError::IoError(why) => LevelIoError::Io(why),
+        why => LevelIoError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, why)),
+    }
+}
+
+/// Source-over blends `color` onto the pixel at (`x`, `y`), ignoring points off the canvas.
+fn blend_pixel(canvas: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>) {
+    if x < 0 || y < 0 || x >= canvas.width() as i64 || y >= canvas.height() as i64 {
+        return;
+    }
+    let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+    let src_alpha = color[3] as f32 / 255.0;
+    let dst_alpha = pixel[3] as f32 / 255.0;
+    let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+    if out_alpha <= 0.0 {
+        return;
+    }
+    for channel in 0..3 {
+        let blended = (color[channel] as f32 * src_alpha
+            + pixel[channel] as f32 * dst_alpha * (1.0 - src_alpha))
+            / out_alpha;
+        pixel[channel] = blended.round() as u8;
+    }
+    pixel[3] = (out_alpha * 255.0).round() as u8;
+}
+
+/// Draws the outline of a marker centered on (`center_x`, `center_y`), in the spirit of the
+/// plot's `MarkerShape`s. `distance` measures how far a pixel is from the center for the shape.
+fn draw_marker(
+    canvas: &mut RgbaImage,
+    (center_x, center_y): (f32, f32),
+    radius: f32,
+    color: Rgba<u8>,
+    distance: impl Fn(f32, f32) -> f32,
+) {
+    let reach = radius.ceil() as i64 + 1;
+    for y in center_y as i64 - reach..=center_y as i64 + reach {
+        for x in center_x as i64 - reach..=center_x as i64 + reach {
+            let d = distance(x as f32 + 0.5 - center_x, y as f32 + 0.5 - center_y);
+            if d <= radius && d > radius - 1.5 {
+                blend_pixel(canvas, x, y, color);
+            }
+        }
+    }
+}
+
+/// Draws the closed outline through `corners`, given in pixels.
+fn draw_outline(canvas: &mut RgbaImage, corners: &[(f32, f32)], color: Rgba<u8>) {
+    for (i, &(x0, y0)) in corners.iter().enumerate() {
+        let (x1, y1) = corners[(i + 1) % corners.len()];
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as i64;
+        for step in 0..steps {
+            let t = step as f32 / steps as f32;
+            let x = x0 + (x1 - x0) * t;
+            let y = y0 + (y1 - y0) * t;
+            blend_pixel(canvas, x.floor() as i64, y.floor() as i64, color);
+        }
+    }
+}
+
+/// Rasterizes `labels` with egui's own fonts on the CPU so they match the editor's text. Each
+/// label is horizontally centered on its x and hangs below its y, pushed back inside the
+/// image if it would run off an edge.
+fn draw_labels(canvas: &mut RgbaImage, labels: &[(&str, (f32, f32))], color: Rgba<u8>) {
+    let fonts = Fonts::new(1.0, 2048, FontDefinitions::default());
+    let galleys: Vec<_> = labels
+        .iter()
+        .map(|(text, position)| {
+            let galley = fonts.layout_no_wrap(
+                text.to_string(),
+                FontId::proportional(LABEL_FONT_SIZE),
+                Color32::WHITE,
+            );
+            (galley, *position)
+        })
+        .collect();
+    // Nothing has taken a delta from this atlas yet, so this is the whole glyph image.
+    let atlas = match fonts.font_image_delta().map(|delta| delta.image) {
+        Some(ImageData::Font(atlas)) => atlas,
+        _ => return,
+    };
+    for (galley, (center_x, top)) in galleys {
+        // Keep labels near the level's edges inside the image.
+        let size = galley.size();
+        let left = (center_x - size.x / 2.0)
+            .min(canvas.width() as f32 - size.x)
+            .max(0.0);
+        let top = top.min(canvas.height() as f32 - size.y).max(0.0);
+        for row in &galley.rows {
+            for glyph in &row.glyphs {
+                let uv = glyph.uv_rect;
+                let origin_x = (left + glyph.pos.x + uv.offset.x).round() as i64;
+                let origin_y = (top + glyph.pos.y + uv.offset.y).round() as i64;
+                for v in uv.min[1]..uv.max[1] {
+                    for u in uv.min[0]..uv.max[0] {
+                        let coverage = atlas.pixels[v as usize * atlas.size[0] + u as usize];
+                        let mut pixel = color;
+                        pixel[3] = (color[3] as f32 * coverage).round() as u8;
+                        blend_pixel(
+                            canvas,
+                            origin_x + (u - uv.min[0]) as i64,
+                            origin_y + (v - uv.min[1]) as i64,
+                            pixel,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fills every cell of `int_grid` with its value's color at the plot's `fill_alpha`.
+fn render_int_grid(
+    canvas: &mut RgbaImage,
+    int_grid: &IntGridLayer,
+    bounds: LevelBounds,
+    (tile_width, tile_height): (u32, u32),
+) {
+    for (point, value) in &int_grid.cells {
+        let [r, g, b] = match int_grid.value(*value) {
+            Some(value) => value.color,
+            None => continue,
+        };
+        let (col, row) = bounds.to_cell(point);
+        let (x, y) = (col * tile_width as i64, row * tile_height as i64);
+        for dy in 0..tile_height as i64 {
+            for dx in 0..tile_width as i64 {
+                blend_pixel(canvas, x + dx, y + dy, Rgba([r, g, b, 102]));
+            }
+        }
+    }
+}
+
+fn render_tiles(
+    level: &Level,
+    canvas: &mut RgbaImage,
+    sheet: Option<&RgbaImage>,
+    tiles: &HashMap<HashableVec2, TileIndex>,
+    bounds: LevelBounds,
+    (tile_width, tile_height): (u32, u32),
+) {
+    for (point, tile) in tiles {
+        let (col, row) = bounds.to_cell(point);
+        let (x, y) = (col * tile_width as i64, row * tile_height as i64);
+        match sheet {
+            Some(sheet) => {
+                let (src_x, src_y) = (tile.col as u32 * tile_width, tile.row as u32 * tile_height);
+                if src_x + tile_width > sheet.width() || src_y + tile_height > sheet.height() {
+                    continue;
+                }
+                let sprite = sheet.view(src_x, src_y, tile_width, tile_height);
+                image::imageops::overlay(canvas, &*sprite, x, y);
+            }
+            None => {
+                // Same colors as `draw_placeholders_on_plot`.
+                let n = tile.row as f32 * level.spritesheet_info.num_rows as f32 + tile.col as f32;
+                let hue = (n * 0.618_034).fract();
+                let color = Color32::from(egui::color::Hsva::new(hue, 0.6, 0.8, 1.0));
+                let color = Rgba([color.r(), color.g(), color.b(), 128]);
+                for dy in 0..tile_height as i64 {
+                    for dx in 0..tile_width as i64 {
+                        blend_pixel(canvas, x + dx, y + dy, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Composites the level at the spritesheet's native resolution, cropped to the level's
+/// bounds. Without a spritesheet tiles are drawn as the plot's colored placeholders.
+fn render_level(level: &Level, options: &RenderOptions) -> Result<RgbaImage, LevelIoError> {
+    let bounds = level.bounds();
+    let tile_size = level.tile_pixel_size();
+    let sheet = match &level.spritesheet {
+        Some(source) => Some(image::open(&source.path).map_err(image_error)?.to_rgba8()),
+        None => None,
+    };
+    let canvas_size = |cells: i64, tile_pixels: u32| {
+        u32::try_from(cells)
+            .ok()
+            .and_then(|cells| cells.checked_mul(tile_pixels))
+    };
+    let (width, height) = match (
+        canvas_size(bounds.width(), tile_size.0),
+        canvas_size(bounds.height(), tile_size.1),
+    ) {
+        (Some(width), Some(height)) if width as u64 * height as u64 <= MAX_RENDER_PIXELS => {
+            (width, height)
+        }
+        _ => {
+            return Err(LevelIoError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "the level is {}x{} cells of {}x{} pixels, too large to render",
+                    bounds.width(),
+                    bounds.height(),
+                    tile_size.0,
+                    tile_size.1
+                ),
+            )))
+        }
+    };
+    let mut canvas = RgbaImage::new(width, height);
+    for (layer, shown) in level.layers.iter().zip(&options.layers) {
+        if *shown {
+            render_tiles(
+                level,
+                &mut canvas,
+                sheet.as_ref(),
+                &layer.tiles,
+                bounds,
+                tile_size,
+            );
+        }
+    }
+    for (int_grid, shown) in level.int_grids.iter().zip(&options.int_grids) {
+        if *shown {
+            render_int_grid(&mut canvas, int_grid, bounds, tile_size);
+        }
+    }
+    let cell_center = |point: &HashableVec2| {
+        let (col, row) = bounds.to_cell(point);
+        (
+            (col as f32 + 0.5) * tile_size.0 as f32,
+            (row as f32 + 0.5) * tile_size.1 as f32,
+        )
+    };
+    let marker_radius = tile_size.0.min(tile_size.1) as f32 * 0.4;
+    if options.collision {
+        for (point, cell) in &level.collision {
+            let [r, g, b] = level
+                .collision_kinds
+                .get(cell.kind as usize)
+                .map_or([255, 0, 0], |kind| kind.color);
+            // inset as far as the entity markers, with y flipped from cell space
+            let (center_x, center_y) = cell_center(point);
+            let corners: Vec<(f32, f32)> = cell
+                .shape
+                .outline()
+                .into_iter()
+                .map(|[x, y]| {
+                    (
+                        center_x + (x - 0.5) * 0.8 * tile_size.0 as f32,
+                        center_y - (y - 0.5) * 0.8 * tile_size.1 as f32,
+                    )
+                })
+                .collect();
+            draw_outline(&mut canvas, &corners, Rgba([r, g, b, 255]));
+        }
+    }
+    if options.entities {
+        let mut labels = Vec::new();
+        for (point, label) in &level.entities {
+            let center = cell_center(point);
+            draw_marker(
+                &mut canvas,
+                center,
+                marker_radius,
+                ENTITY_COLOR,
+                |dx, dy| dx.abs() + dy.abs(),
+            );
+            labels.push((label.as_str(), (center.0, center.1 + marker_radius + 1.0)));
+        }
+        draw_labels(&mut canvas, &labels, ENTITY_COLOR);
+    }
+    Ok(canvas)
+}
+
+/// Renders `level` to a PNG at `path`, as `lvl-tool` run through the editor binary does.
+pub fn render_png(level: &Level, options: &RenderOptions, path: &Path) -> Result<(), LevelIoError> {
+    render_level(level, options)?
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(image_error)
+}
+
+impl MyApp {
+    /// The layers the view filters currently show.
+    pub(crate) fn visible_layers(&self) -> RenderOptions {
+        RenderOptions {
+            layers: self.layer_views.iter().map(|view| view.visible).collect(),
+            int_grids: self
+                .int_grid_views
+                .iter()
+                .map(|view| view.visible)
+                .collect(),
+            collision: self.collision_view.visible,
+            entities: self.entity_view.visible,
+        }
+    }
+
+    pub(crate) fn export_png(
+        &self,
+        path: PathBuf,
+        options: RenderOptions,
+    ) -> Result<(), LevelIoError> {
+        render_png(&self.level, &options, &path)
+    }
+}
@@ -1,9 +1,9 @@
 use std::path::PathBuf;
 
 use eframe::egui;
-use egui::{Pos2, Rect, Vec2};
+use egui::Vec2;
 
-use crate::file::pick_file_to;
+use crate::file::{hash_bytes, pick_file_to, IMAGE_EXTENSIONS};
 use crate::types::*;
 use crate::MyApp;
 
@@ -11,15 +11,31 @@ impl MyApp {
     fn side_panel_settings(&mut self, ui: &mut egui::Ui) {
         ui.collapsing("Settings", |ui| {
             ui.label("Sprite Size");
-            ui.add(
-                egui::DragValue::new(&mut self.spritesheet_info.sprite_size)
-                    .clamp_range(1..=1024)
-                    .suffix("px"),
-            );
+            let mut changed = ui
+                .add(
+                    egui::DragValue::new(&mut self.level.spritesheet_info.sprite_size)
+                        .clamp_range(1..=1024)
+                        .suffix("px"),
+                )
+                .changed();
             ui.label("Num Rows");
-            ui.add(egui::DragValue::new(&mut self.spritesheet_info.num_rows).clamp_range(1..=255));
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.level.spritesheet_info.num_rows)
+                        .clamp_range(1..=255),
+                )
+                .changed();
             ui.label("Num Columns");
-            ui.add(egui::DragValue::new(&mut self.spritesheet_info.num_cols).clamp_range(1..=255));
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.level.spritesheet_info.num_cols)
+                        .clamp_range(1..=255),
+                )
+                .changed();
+            // the spritesheet settings are saved with the level
+            if changed {
+                self.mark_modified();
+            }
             ui.separator();
             ui.label("Preview Display");
             ui.radio_value(
@@ -36,66 +52,48 @@ impl MyApp {
     }
     fn side_panel_sprite_selector_make_img_btn(
         &self,
-        x: u32,
-        y: u32,
+        tile: TileIndex,
         handle: &egui::TextureHandle,
-    ) -> (egui::ImageButton, Rect) {
+    ) -> egui::ImageButton {
         let handle_size = handle.size_vec2();
-        let normalized_x = x as f32 / handle_size.x;
-        let normalized_y = y as f32 / handle_size.y;
-        let max_x =
-            (x as f32 + handle_size.x / self.spritesheet_info.num_rows as f32) / handle_size.x;
-        let max_y =
-            (y as f32 + handle_size.y / self.spritesheet_info.num_cols as f32) / handle_size.y;
-        let uv = Rect {
-            min: Pos2 {
-                x: normalized_x,
-                y: normalized_y,
-            },
-            max: Pos2 { x: max_x, y: max_y },
-        };
         let mut img_btn = egui::widgets::ImageButton::new(
             handle,
             Vec2 {
-                x: handle_size.x / self.spritesheet_info.num_rows as f32,
-                y: handle_size.y / self.spritesheet_info.num_cols as f32,
+                x: handle_size.x / self.level.spritesheet_info.num_rows as f32,
+                y: handle_size.y / self.level.spritesheet_info.num_cols as f32,
             },
         )
-        .uv(uv);
-        if self.selected_uv == Some(uv) {
+        .uv(tile_uv(&self.level.spritesheet_info, tile));
+        if self.selected_tile == Some(tile) {
             img_btn = img_btn.selected(true);
         }
-        (img_btn, uv)
+        img_btn
     }
     fn side_panel_sprite_selector(&mut self, ui: &mut egui::Ui) {
         ui.horizontal_wrapped(|ui| {
             if let Some(handle) = &self.spritesheet_handle {
-                let handle_size = handle.size_vec2();
+                // num_rows counts tiles along the x axis, see tile_uv
+                let tiles_across = self.level.spritesheet_info.num_rows as u16;
+                let tiles_down = self.level.spritesheet_info.num_cols as u16;
                 if matches!(self.spritesheet_col_orientation, ColumnOrientation::Major) {
-                    for x in (0..handle_size.x as u32)
-                        .step_by((handle_size.x / self.spritesheet_info.num_rows as f32) as usize)
-                    {
-                        for y in (0..handle_size.y as u32).step_by(
-                            (handle_size.y / self.spritesheet_info.num_cols as f32) as usize,
-                        ) {
-                            let (img_btn, uv) =
-                                self.side_panel_sprite_selector_make_img_btn(x, y, handle);
+                    for col in 0..tiles_across {
+                        for row in 0..tiles_down {
+                            let tile = TileIndex { col, row };
+                            let img_btn =
+                                self.side_panel_sprite_selector_make_img_btn(tile, handle);
                             if ui.add(img_btn).clicked() {
-                                self.selected_uv = Some(uv);
+                                self.selected_tile = Some(tile);
                             };
                         }
                     }
                 } else {
-                    for y in (0..handle_size.y as u32)
-                        .step_by((handle_size.y / self.spritesheet_info.num_cols as f32) as usize)
-                    {
-                        for x in (0..handle_size.x as u32).step_by(
-                            (handle_size.x / self.spritesheet_info.num_rows as f32) as usize,
-                        ) {
-                            let (img_btn, uv) =
-                                self.side_panel_sprite_selector_make_img_btn(x, y, handle);
+                    for row in 0..tiles_down {
+                        for col in 0..tiles_across {
+                            let tile = TileIndex { col, row };
+                            let img_btn =
+                                self.side_panel_sprite_selector_make_img_btn(tile, handle);
                             if ui.add(img_btn).clicked() {
-                                self.selected_uv = Some(uv);
+                                self.selected_tile = Some(tile);
                             };
                         }
                     }
@@ -103,24 +101,58 @@ impl MyApp {
             }
         });
     }
+    fn load_spritesheet(&mut self, ctx: &egui::Context, path: PathBuf) {
+        let bytes = match std::fs::read(&path) {
+            Err(why) => {
+                self.error_message = Some(format!("Couldn't open {}: {}", path.display(), why));
+                return;
+            }
+            Ok(bytes) => bytes,
+        };
+        let image = match image::load_from_memory(&bytes) {
+            Err(why) => {
+                self.error_message = Some(format!("Couldn't open {}: {}", path.display(), why));
+                return;
+            }
+            Ok(image) => image,
+        };
+        let size = [image.width() as usize, image.height() as usize];
+        let image_buffer = image.to_rgba8();
+        let pixels = image_buffer.as_flat_samples();
+        let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
+        self.spritesheet_handle =
+            Some(ctx.load_texture("example-image", color_image, egui::TextureFilter::Nearest));
+        let source = SpritesheetSource {
+            path,
+            width: image.width(),
+            height: image.height(),
+            hash: Some(hash_bytes(&bytes)),
+        };
+        // opening a level loads the sheet it already names, which isn't a change
+        let same_sheet = self
+            .level
+            .spritesheet
+            .as_ref()
+            .is_some_and(|current| current.path == source.path && current.hash == source.hash);
+        if !same_sheet {
+            self.mark_modified();
+        }
+        self.level.spritesheet = Some(source);
+    }
     fn side_panel_spritesheet_preview(
         &mut self,
         ctx: &egui::Context,
         ui: &mut egui::Ui,
         spritesheet_path: Option<PathBuf>,
     ) {
-        if let Some(path) = spritesheet_path {
-            let image_result = image::io::Reader::open(path).unwrap().decode();
-            if image_result.is_err() {
-                return;
-            }
-            let image = image_result.unwrap();
-            let size = [image.width() as usize, image.height() as usize];
-            let image_buffer = image.to_rgba8();
-            let pixels = image_buffer.as_flat_samples();
-            let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
-            self.spritesheet_handle =
-                Some(ctx.load_texture("example-image", color_image, egui::TextureFilter::Nearest));
+        if self.missing_spritesheet.take().is_some() {
+            pick_file_to(
+                &mut self.pending_spritesheet_path,
+                ("Locate spritesheet", IMAGE_EXTENSIONS),
+            );
+        }
+        if let Some(path) = spritesheet_path.or_else(|| self.pending_spritesheet_path.take()) {
+            self.load_spritesheet(ctx, path);
         }
         if let Some(handle) = &self.spritesheet_handle {
             ui.image(handle, handle.size_vec2());
@@ -131,10 +163,7 @@ impl MyApp {
             egui::ScrollArea::vertical().show(ui, |ui| {
                 let mut spritesheet_path = None;
                 if ui.button("Open Spritesheet").clicked() {
-                    pick_file_to(
-                        &mut spritesheet_path,
-                        ("image", &["webp", "png", "bmp", "jpg", "jpeg"]),
-                    );
+                    pick_file_to(&mut spritesheet_path, ("image", IMAGE_EXTENSIONS));
                 }
                 self.side_panel_spritesheet_preview(ctx, ui, spritesheet_path);
                 self.side_panel_settings(ui);
@@ -5,42 +5,201 @@ use crate::types::*;
 use crate::MyApp;
 
 impl MyApp {
+    /// Asks for a file of this kind and replaces the level with it, see `PendingAction::Import`.
+    pub(crate) fn import_from_picker(&mut self, kind: ImportKind) {
+        let mut import_path = None;
+        match kind {
+            ImportKind::Json => {
+                pick_file_to(&mut import_path, ("JSON", &["json"]));
+                if let Some(path) = import_path {
+                    let result = self.import_json(path.clone());
+                    self.report_io_error("import", &path, result);
+                }
+            }
+            ImportKind::Tiled => {
+                pick_file_to(&mut import_path, ("Tiled", &["tmx", "tmj"]));
+                if let Some(path) = import_path {
+                    match self.import_tiled(path.clone()) {
+                        Ok(report) => {
+                            self.show_io_report("Some of the map could not be imported", report)
+                        }
+                        Err(why) => self.report_io_error("import", &path, Err(why)),
+                    }
+                }
+            }
+            ImportKind::Csv => {
+                pick_file_to(&mut import_path, ("CSV Sidecar", &["json"]));
+                if let Some(path) = import_path {
+                    let result = self.import_csv(path.clone());
+                    self.report_io_error("import", &path, result);
+                }
+            }
+        }
+    }
+
+    fn top_panel_import_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Import", |ui| {
+            if ui.button("JSON").clicked() {
+                ui.close_menu();
+                self.request(PendingAction::Import(ImportKind::Json));
+            } else if ui.button("Tiled Map").clicked() {
+                ui.close_menu();
+                self.request(PendingAction::Import(ImportKind::Tiled));
+            } else if ui
+                .button("CSV Grids")
+                .on_hover_text("Pick the .origin.json sidecar")
+                .clicked()
+            {
+                ui.close_menu();
+                self.request(PendingAction::Import(ImportKind::Csv));
+            }
+        });
+    }
+    fn top_panel_export_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Export", |ui| {
+            let mut export_path = None;
+            if ui.button("JSON").clicked() {
+                ui.close_menu();
+                save_file_to(&mut export_path, &[("JSON", &["json"])]);
+                if let Some(path) = export_path {
+                    let result = self.level.export_json(&path);
+                    self.report_io_error("export", &path, result);
+                }
+            } else if ui.button("Tiled Map").clicked() {
+                ui.close_menu();
+                save_file_to(&mut export_path, &[("Tiled", &["tmx", "tmj"])]);
+                if let Some(path) = export_path {
+                    match self.level.export_tiled(&path) {
+                        Ok(report) => {
+                            self.show_io_report("Some of the level could not be exported", report)
+                        }
+                        Err(why) => self.report_io_error("export", &path, Err(why)),
+                    }
+                }
+            } else if ui.button("LDtk Project").clicked() {
+                ui.close_menu();
+                save_file_to(&mut export_path, &[("LDtk", &["ldtk"])]);
+                if let Some(path) = export_path {
+                    match self.level.export_ldtk(&path) {
+                        Ok(report) => {
+                            self.show_io_report("Some of the level could not be exported", report)
+                        }
+                        Err(why) => self.report_io_error("export", &path, Err(why)),
+                    }
+                }
+            } else if ui
+                .button("PNG Image")
+                .on_hover_text("Renders the layers currently shown")
+                .clicked()
+            {
+                ui.close_menu();
+                save_file_to(&mut export_path, &[("PNG", &["png"])]);
+                if let Some(path) = export_path {
+                    let result = self.export_png(path.clone(), self.visible_layers());
+                    self.report_io_error("export", &path, result);
+                }
+            } else if ui
+                .button("CSV Grids")
+                .on_hover_text("One CSV per layer plus a .origin.json sidecar")
+                .clicked()
+            {
+                ui.close_menu();
+                save_file_to(&mut export_path, &[("CSV", &["csv"])]);
+                if let Some(path) = export_path {
+                    let result = self.level.export_csv(&path);
+                    self.report_io_error("export", &path, result);
+                }
+            }
+        });
+    }
+    fn top_panel_recent_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Recent", |ui| {
+            if self.recent_files.is_empty() {
+                ui.label("No recent files");
+                return;
+            }
+            let mut chosen = None;
+            for path in &self.recent_files {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if ui
+                    .button(name)
+                    .on_hover_text(path.display().to_string())
+                    .clicked()
+                {
+                    chosen = Some(path.clone());
+                }
+            }
+            ui.separator();
+            if ui.button("Clear Recent").clicked() {
+                ui.close_menu();
+                self.clear_recent_files();
+            }
+            if let Some(path) = chosen {
+                ui.close_menu();
+                self.request(PendingAction::OpenRecent(path));
+            }
+        });
+    }
     pub(crate) fn top_panel(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("my_top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                let mut save_path = None;
-                let mut open_path = None;
                 if ui.small_button("Save").on_hover_text("Ctrl + S").clicked() {
-                    save_file_to(&mut save_path, ("Level", &["lvl"]));
-                    if let Some(path) = save_path {
-                        self.save(path);
-                    }
+                    self.save_level();
+                }
+                if ui
+                    .small_button("Save As")
+                    .on_hover_text("Ctrl + Shift + S")
+                    .clicked()
+                {
+                    self.save_level_as();
                 }
                 if ui.small_button("Open").on_hover_text("Ctrl + O").clicked() {
-                    pick_file_to(&mut open_path, ("Level", &["lvl"]));
-                    if let Some(path) = open_path {
-                        self.open(path);
-                    }
+                    self.request(PendingAction::Open);
                 }
+                self.top_panel_recent_menu(ui);
+                if ui
+                    .checkbox(&mut self.level.compressed, "Compress")
+                    .on_hover_text("Save .lvl files deflated, for large levels")
+                    .changed()
+                {
+                    self.mark_modified();
+                }
+                self.top_panel_import_menu(ui);
+                self.top_panel_export_menu(ui);
                 ui.separator();
                 if ui.small_button("Clear").clicked() {
-                    self.show_clear_confirmation = true;
+                    self.request(PendingAction::Clear);
                 }
                 ui.separator();
-                ui.radio_value(&mut self.current_mode, Mode::DrawBackground, "Background")
-                    .on_hover_text("M");
-                ui.radio_value(&mut self.current_mode, Mode::DrawForeground, "Foreground")
+                let tiles_label = match self.level.layers.get(self.current_layer) {
+                    Some(layer) => format!("Tiles ({})", layer.name),
+                    None => "Tiles".to_string(),
+                };
+                ui.radio_value(&mut self.current_mode, Mode::DrawTiles, tiles_label)
                     .on_hover_text("M");
                 ui.radio_value(&mut self.current_mode, Mode::Collision, "Collision")
                     .on_hover_text("M");
+                if let Some(layer) = self.level.int_grids.get(self.current_int_grid) {
+                    let int_grid_label = format!("Int Grid ({})", layer.name);
+                    ui.radio_value(&mut self.current_mode, Mode::IntGrid, int_grid_label)
+                        .on_hover_text("M");
+                }
                 ui.radio_value(&mut self.current_mode, Mode::Entity, "Entity")
                     .on_hover_text("M");
                 ui.separator();
                 ui.label("View Filter");
-                ui.checkbox(&mut self.show_foreground, "Foreground");
-                ui.checkbox(&mut self.show_background, "Background");
-                ui.checkbox(&mut self.show_collision, "Collision");
-                ui.checkbox(&mut self.show_entity, "Entity");
+                // top layer first, like the layers panel
+                for (layer, view) in self.level.layers.iter().zip(&mut self.layer_views).rev() {
+                    ui.checkbox(&mut view.visible, &layer.name);
+                }
+                ui.checkbox(&mut self.collision_view.visible, "Collision");
+                for (layer, view) in self.level.int_grids.iter().zip(&mut self.int_grid_views) {
+                    ui.checkbox(&mut view.visible, &layer.name);
+                }
+                ui.checkbox(&mut self.entity_view.visible, "Entity");
                 ui.checkbox(&mut self.show_grid, "Grid");
             });
         });
@@ -1,51 +1,52 @@
+use std::path::PathBuf;
+
 use eframe::egui;
-use eframe::egui::Rect;
+use eframe::egui::{Pos2, Rect};
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-pub struct HashableVec2 {
-    pub x: i64,
-    pub y: i64,
-}
+pub use rs_level::{
+    CollisionCell, CollisionKind, CollisionShape, HashableVec2, IntGridLayer, IntGridValue,
+    LevelBounds, SpritesheetInfo, SpritesheetSource, TileIndex, TileLayer,
+};
 
-impl From<egui::widgets::plot::PlotPoint> for HashableVec2 {
-    fn from(point: egui::widgets::plot::PlotPoint) -> HashableVec2 {
-        HashableVec2 {
-            x: point.x as i64,
-            y: point.y as i64,
-        }
+/// The cell a plot coordinate falls in.
+pub fn plot_cell(point: egui::widgets::plot::PlotPoint) -> HashableVec2 {
+    HashableVec2 {
+        x: point.x as i64,
+        y: point.y as i64,
     }
 }
 
-impl From<HashableVec2> for egui::widgets::plot::PlotPoint {
-    fn from(point: HashableVec2) -> egui::widgets::plot::PlotPoint {
-        egui::widgets::plot::PlotPoint {
-            x: point.x as f64,
-            y: point.y as f64,
-        }
+/// Where a tile sits on the spritesheet texture, in normalized texture coordinates.
+pub fn tile_uv(info: &SpritesheetInfo, index: TileIndex) -> Rect {
+    let width = 1.0 / info.num_rows as f32;
+    let height = 1.0 / info.num_cols as f32;
+    Rect {
+        min: Pos2 {
+            x: index.col as f32 * width,
+            y: index.row as f32 * height,
+        },
+        max: Pos2 {
+            x: (index.col + 1) as f32 * width,
+            y: (index.row + 1) as f32 * height,
+        },
     }
 }
 
-pub struct SpritesheetInfo {
-    pub sprite_size: u16,
-    pub num_rows: u8,
-    pub num_cols: u8,
-}
-
-impl Default for SpritesheetInfo {
-    fn default() -> Self {
-        Self {
-            sprite_size: 32,
-            num_rows: 10,
-            num_cols: 10,
-        }
-    }
+/// A color for the `index`th entry added to a palette, spread out so neighbours differ. The plot
+/// colors placeholder tiles the same way.
+pub fn palette_color(index: usize) -> [u8; 3] {
+    let hue = (index as f32 * 0.618_034).fract();
+    let color = egui::Color32::from(egui::color::Hsva::new(hue, 0.6, 0.8, 1.0));
+    [color.r(), color.g(), color.b()]
 }
 
 #[derive(PartialEq, Debug)]
 pub enum Mode {
-    DrawBackground,
-    DrawForeground,
+    /// Draw on the tile layer at `MyApp::current_layer`.
+    DrawTiles,
     Collision,
+    /// Paint on the int grid at `MyApp::current_int_grid`.
+    IntGrid,
     Entity,
 }
 
@@ -55,11 +56,81 @@ pub enum ColumnOrientation {
     Minor,
 }
 
-#[expect(clippy::enum_variant_names)]
+/// The formats a level can be imported from.
+#[derive(Debug, Clone, Copy)]
+pub enum ImportKind {
+    Json,
+    Tiled,
+    /// CSV grids, picked by their `.origin.json` sidecar.
+    Csv,
+}
+
+/// Something that would throw away unsaved changes, held while the user decides about them.
+#[derive(Debug, Clone)]
+pub enum PendingAction {
+    /// Pick a level to open.
+    Open,
+    OpenRecent(PathBuf),
+    /// Pick a file to import.
+    Import(ImportKind),
+    Clear,
+    Close,
+}
+
+/// How the editor shows a tile layer or overlay. Tile layers and int grids have one each, kept in
+/// step with `Level::layers` and `Level::int_grids`. Not saved with the level.
+#[derive(Debug, Clone)]
+pub struct LayerView {
+    pub visible: bool,
+    /// Clicks on the plot can pick from a locked layer but not edit it.
+    pub locked: bool,
+    /// While any layer is soloed, the others are dimmed.
+    pub solo: bool,
+    /// From 0 to 1.
+    pub opacity: f32,
+}
+
+impl Default for LayerView {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            locked: false,
+            solo: false,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// An edit in the undo queue. Handling an action undoes it and queues the action that redoes it.
 #[derive(Debug, Clone)]
 pub enum Action {
-    ClickForeground(HashableVec2, Rect, Option<Rect>, bool),
-    ClickBackground(HashableVec2, Rect, Option<Rect>, bool),
-    ClickCollision(HashableVec2),
+    /// Layer index, point, placed tile, replaced tile, whether it was placed by dragging.
+    ClickTile(usize, HashableVec2, TileIndex, Option<TileIndex>, bool),
+    /// Point, placed collision cell, replaced cell, whether it was placed by dragging.
+    ClickCollision(HashableVec2, CollisionCell, Option<CollisionCell>, bool),
     ClickEntity(HashableVec2, Option<String>),
+    /// A layer was added at this index.
+    AddLayer(usize),
+    /// This layer was removed from this index.
+    RemoveLayer(usize, TileLayer),
+    /// The layer at this index was renamed from this name.
+    RenameLayer(usize, String),
+    /// A layer was moved from the first index to the second.
+    MoveLayer(usize, usize),
+    /// A collision kind was added at this index.
+    AddCollisionKind(usize),
+    /// This unused collision kind was removed from this index.
+    RemoveCollisionKind(usize, CollisionKind),
+    /// Int grid index, point, placed value, replaced value, whether it was placed by dragging.
+    ClickIntGrid(usize, HashableVec2, u32, Option<u32>, bool),
+    /// An int grid was added at this index.
+    AddIntGrid(usize),
+    /// This int grid was removed from this index.
+    RemoveIntGrid(usize, IntGridLayer),
+    /// The int grid at this index was renamed from this name.
+    RenameIntGrid(usize, String),
+    /// A value was added to the int grid at the first index, at the second.
+    AddIntGridValue(usize, usize),
+    /// This unused value was removed from the int grid at the first index, from the second.
+    RemoveIntGridValue(usize, usize, IntGridValue),
 }
@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use crate::file::{pick_file_to, save_file_to, LEVEL_FILTER, LEVEL_SAVE_FILTERS};
+use crate::types::*;
+use crate::MyApp;
+
+impl MyApp {
+    /// Whether the level differs from the file it was last opened from or saved to. Edits are
+    /// tracked through the undo queue, so undoing back to the saved state counts as unmodified.
+    pub(crate) fn is_modified(&self) -> bool {
+        self.saved_undo_len != Some(self.undo_queue.len())
+    }
+
+    /// Called once the level matches what is on disk.
+    pub(crate) fn mark_saved(&mut self) {
+        self.saved_undo_len = Some(self.undo_queue.len());
+    }
+
+    /// For changes that don't go through the undo queue, e.g. renaming an entity.
+    pub(crate) fn mark_modified(&mut self) {
+        self.saved_undo_len = None;
+    }
+
+    /// Adds a new edit to the undo queue. Redo is no longer possible afterwards, so if the saved
+    /// state was only reachable by redoing it never will be again.
+    pub(crate) fn push_action(&mut self, action: Action) {
+        if self
+            .saved_undo_len
+            .is_some_and(|len| len > self.undo_queue.len())
+        {
+            self.saved_undo_len = None;
+        }
+        self.undo_queue.push(action);
+        self.redo_queue.clear();
+    }
+
+    /// "level.lvl* - Level Editor", with the asterisk while there are unsaved changes.
+    pub(crate) fn update_window_title(&mut self, frame: &mut eframe::Frame) {
+        let name = match &self.level_path {
+            Some(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            None => "Untitled".to_string(),
+        };
+        let marker = if self.is_modified() { "*" } else { "" };
+        let title = format!("{}{} - Level Editor", name, marker);
+        if title != self.window_title {
+            frame.set_window_title(&title);
+            self.window_title = title;
+        }
+    }
+
+    fn save_to(&mut self, path: PathBuf) -> bool {
+        let result = self.save(path.clone());
+        let saved = result.is_ok();
+        self.report_io_error("save", &path, result);
+        if saved {
+            self.remember_recent_file(path);
+        }
+        saved
+    }
+
+    /// Saves over the file the level came from, asking where to save when there isn't one.
+    /// Returns whether the level was saved.
+    pub(crate) fn save_level(&mut self) -> bool {
+        match self.level_path.clone() {
+            Some(path) => self.save_to(path),
+            None => self.save_level_as(),
+        }
+    }
+
+    /// Asks where to save the level, which is then saved there from now on.
+    pub(crate) fn save_level_as(&mut self) -> bool {
+        let mut save_path = None;
+        save_file_to(&mut save_path, LEVEL_SAVE_FILTERS);
+        match save_path {
+            Some(path) => self.save_to(path),
+            None => false,
+        }
+    }
+
+    fn open_from(&mut self, path: PathBuf) {
+        let result = self.open(path.clone());
+        let opened = result.is_ok();
+        self.report_io_error("open", &path, result);
+        if opened {
+            self.remember_recent_file(path);
+        }
+    }
+
+    /// Runs `action` now if nothing would be lost, otherwise asks about the unsaved changes first.
+    pub(crate) fn request(&mut self, action: PendingAction) {
+        if self.is_modified() {
+            self.unsaved_changes_prompt = Some(action);
+        } else {
+            match action {
+                PendingAction::Clear => self.show_clear_confirmation = true,
+                action => self.continue_with(action),
+            }
+        }
+    }
+
+    /// Carries out an action once the user is done with the unsaved changes prompt.
+    pub(crate) fn continue_with(&mut self, action: PendingAction) {
+        self.unsaved_changes_prompt = None;
+        match action {
+            PendingAction::Open => {
+                let mut open_path = None;
+                pick_file_to(&mut open_path, LEVEL_FILTER);
+                if let Some(path) = open_path {
+                    self.open_from(path);
+                }
+            }
+            PendingAction::OpenRecent(path) => self.open_from(path),
+            PendingAction::Import(kind) => self.import_from_picker(kind),
+            PendingAction::Clear => self.clear(),
+            PendingAction::Close => {
+                self.close_confirmed = true;
+                self.close_requested = true;
+            }
+        }
+    }
+
+    /// Closes the window once quitting has been confirmed, see `on_close_event`.
+    pub(crate) fn handle_close_request(&mut self, frame: &mut eframe::Frame) {
+        if self.close_requested {
+            self.close_requested = false;
+            frame.close();
+        }
+    }
+}